@@ -0,0 +1,185 @@
+use rss::extension::dublincore::DublinCoreExtension;
+use rss::{GuidBuilder, Item, ItemBuilder};
+
+use crate::datastructs::ItemMetadata;
+use crate::formatrules::format_rules;
+use crate::formatter::PreppedMessage;
+
+// Every item that is downloaded is kept in the articles table, so the
+// articles stay available after they drop out of the feeds. An article is
+// identified by its PMID, else its doi, so the same article in several feeds
+// is stored once. The titles and abstracts are indexed with FTS5 for /search.
+
+/// Number of articles on a page of search results.
+pub const SEARCH_PAGE_SIZE: usize = 5;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Article {
+    /// "pmid:N", "doi:X", or the link or guid of items without identifiers.
+    pub key: String,
+    pub pmid: Option<u32>,
+    pub doi: Option<String>,
+    pub title: String,
+    pub journal: Option<String>,
+    pub authors: Vec<String>,
+    pub abstract_text: Option<String>,
+    pub link: Option<String>,
+    /// The publication date, as in `PreppedMessage::date`.
+    pub published: Option<String>,
+}
+
+impl Article {
+    /// None for items that can not be identified.
+    pub fn from_item(item: &Item) -> Option<Article> {
+        let item_metadata = ItemMetadata::default();
+        let message = PreppedMessage::build(item, &item_metadata);
+        let pmid = message.pmid.as_deref().and_then(|pmid| pmid.parse().ok());
+        let doi = message.doi.as_deref().map(|doi| doi.to_lowercase());
+        let key = match (pmid, &doi) {
+            (Some(pmid), _) => format!("pmid:{}", pmid),
+            (None, Some(doi)) => format!("doi:{}", doi),
+            (None, None) => item
+                .link()
+                .or(item.guid().map(|guid| guid.value()))
+                .filter(|key| !key.is_empty())?
+                .to_string(),
+        };
+        Some(Article {
+            key,
+            pmid,
+            doi,
+            title: message.plain_title(),
+            journal: message.journal.clone(),
+            authors: message.authors.clone(),
+            abstract_text: message.plain_abstract(),
+            link: message.link.clone(),
+            published: message.date.clone(),
+        })
+    }
+
+    /// Rebuilds an item, so the article can be filtered and sent like the items of the feeds.
+    pub fn to_item(&self) -> Item {
+        let mut identifiers: Vec<String> = self
+            .pmid
+            .iter()
+            .map(|pmid| format!("pmid:{}", pmid))
+            .collect();
+        if let Some(doi) = &self.doi {
+            identifiers.push(format!("doi:{}", doi));
+        }
+        let dublin_core = DublinCoreExtension {
+            creators: self.authors.clone(),
+            dates: self.published.iter().cloned().collect(),
+            identifiers,
+            sources: self.journal.iter().cloned().collect(),
+            titles: vec![self.title.clone()],
+            ..Default::default()
+        };
+        let content = self.abstract_text.as_ref().map(|text| {
+            text.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        });
+        ItemBuilder::default()
+            .title(Some(self.title.clone()))
+            .link(self.link.clone())
+            .guid(Some(
+                GuidBuilder::default()
+                    .value(self.key.clone())
+                    .permalink(false)
+                    .build(),
+            ))
+            .content(content)
+            .dublin_core_ext(Some(dublin_core))
+            .build()
+    }
+
+    pub fn to_message<'a>(&self, item_metadata: &'a ItemMetadata) -> PreppedMessage<'a> {
+        PreppedMessage {
+            title: self.title.clone(),
+            link: self.link.clone(),
+            journal: self.journal.clone(),
+            authors: self.authors.clone(),
+            date: self.published.clone(),
+            content: self.abstract_text.clone(),
+            pmid: self.pmid.map(|pmid| pmid.to_string()),
+            doi: self.doi.clone(),
+            item_metadata,
+            format_rule: format_rules().get(item_metadata.feed_id, self.journal.as_deref()),
+        }
+    }
+}
+
+/// Turns the words and "quoted phrases" of a search into an FTS5 query that
+/// matches all of them. The FTS5 syntax itself is not exposed, so a stray
+/// quote or dash can not make the query fail. None if there is nothing to search.
+pub fn fts_query(search: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    for (index, part) in search.split('"').enumerate() {
+        // Odd parts are between quotes
+        if index % 2 == 1 {
+            let phrase: Vec<&str> = part
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .collect();
+            if !phrase.is_empty() {
+                terms.push(format!("\"{}\"", phrase.join(" ")));
+            }
+        } else {
+            terms.extend(
+                part.split(|c: char| !c.is_alphanumeric())
+                    .filter(|word| !word.is_empty())
+                    .map(|word| format!("\"{}\"", word)),
+            );
+        }
+    }
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channelwrapper::ChannelWrapper;
+
+    #[test]
+    fn test_from_item() {
+        let json = std::fs::read_to_string("test/channel_radiology.json").unwrap();
+        let cw = ChannelWrapper::from_json(&json).unwrap();
+        let article = Article::from_item(&cw.items()[0]).unwrap();
+        assert_eq!(article.key, format!("pmid:{}", article.pmid.unwrap()));
+        assert!(!article.title.is_empty());
+        assert!(!article.authors.is_empty());
+        assert!(article.journal.is_some());
+
+        let item = rss::ItemBuilder::default()
+            .title(Some("An article".to_string()))
+            .link(Some("https://journal.example.org/1".to_string()))
+            .build();
+        let article = Article::from_item(&item).unwrap();
+        assert_eq!(article.key, "https://journal.example.org/1");
+        assert_eq!(article.pmid, None);
+        assert!(Article::from_item(&rss::Item::default()).is_none());
+    }
+
+    #[test]
+    fn test_to_item() {
+        let json = std::fs::read_to_string("test/channel_radiology.json").unwrap();
+        let cw = ChannelWrapper::from_json(&json).unwrap();
+        let article = Article::from_item(&cw.items()[0]).unwrap();
+        // The same article after a round trip
+        assert_eq!(Article::from_item(&article.to_item()).unwrap(), article);
+    }
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(
+            fts_query("adrenal vein-sampling").as_deref(),
+            Some(r#""adrenal" "vein" "sampling""#)
+        );
+        assert_eq!(
+            fts_query(r#"covid "long covid" AND"#).as_deref(),
+            Some(r#""covid" "long covid" "AND""#)
+        );
+        assert_eq!(fts_query(r#" "" - "#), None);
+    }
+}
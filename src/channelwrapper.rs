@@ -1,14 +1,18 @@
 use chrono::ParseError;
+use chrono::TimeDelta;
 use chrono::prelude::*;
 use rss::Channel;
 use rss::Item;
+use rss::extension::dublincore::DublinCoreExtension;
+use rss::{ChannelBuilder, GuidBuilder, ItemBuilder};
 use rusqlite::types::FromSql;
 use rusqlite::types::FromSqlError;
 use rusqlite::types::FromSqlResult;
 use rusqlite::types::ValueRef;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
 use std::ops::Deref;
-use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ChannelWrapper(Channel);
@@ -39,35 +43,188 @@ impl ChannelWrapper {
         let prev: DateTime<FixedOffset> = DateTime::parse_from_rfc2822(fromdate)?;
         let mut new_items: Vec<&Item> = Vec::new();
         for item in self.items() {
-            if let Some(pub_date) = item.pub_date() {
-                if DateTime::parse_from_rfc2822(pub_date).unwrap() > prev {
-                    new_items.push(item);
-                }
+            match item.pub_date().map(DateTime::parse_from_rfc2822) {
+                Some(Ok(pub_date)) if pub_date > prev => new_items.push(item),
+                Some(Err(e)) => log::warn!(
+                    "Skipped item with an invalid date ({e}): {}",
+                    item.title().unwrap_or("no title")
+                ),
+                _ => (),
             }
         }
         Ok(new_items)
     }
-    pub fn get_new_items_from_last<'a>(&'a self, guid: &u32) -> Vec<&'a Item> {
-        let mut new_items: Vec<&Item> = Vec::new();
-        for item in self.items() {
-            if ChannelWrapper::parse_guid(item).unwrap_or(0) <= *guid {
-                break;
-            }
-            new_items.push(item);
-        }
-        new_items
+
+    /// The average time between the publication dates of the items, None if
+    /// there are less than two different dates.
+    pub fn average_item_gap(&self) -> Option<TimeDelta> {
+        let mut dates: Vec<DateTime<FixedOffset>> = self
+            .items()
+            .iter()
+            .filter_map(|item| DateTime::parse_from_rfc2822(item.pub_date()?).ok())
+            .collect();
+        dates.sort();
+        dates.dedup();
+        let (first, last) = (dates.first()?, dates.last()?);
+        (dates.len() >= 2).then(|| (*last - *first) / (dates.len() as i32 - 1))
+    }
+
+    /// The items that are not in the set of seen item ids (see `item_ids`),
+    /// in the order of the feed. Malformed items are skipped.
+    pub fn get_unseen_items<'a>(&'a self, seen: &HashSet<u32>) -> Vec<&'a Item> {
+        self.item_ids()
+            .into_iter()
+            .filter(|(_, id)| !seen.contains(id))
+            .map(|(item, _)| item)
+            .collect()
+    }
+
+    /// The items that can not be sent, with the reason.
+    pub fn malformed_items(&self) -> Vec<(&Item, &'static str)> {
+        self.items()
+            .iter()
+            .filter_map(|item| {
+                if !self.is_pubmed() {
+                    ChannelWrapper::item_id(item)
+                        .is_none()
+                        .then_some((item, "no guid, link or title"))
+                } else if item.guid().is_none() {
+                    Some((item, "no guid"))
+                } else if ChannelWrapper::parse_guid(item).is_none() {
+                    Some((item, "guid is not a number"))
+                } else {
+                    None
+                }
+            })
+            .collect()
     }
 
-    pub fn parse_guid(item: &Item) -> Result<u32, <u32 as FromStr>::Err> {
+    /// PubMed feeds have guids like "pubmed:40232138".
+    pub fn is_pubmed(&self) -> bool {
+        self.items().iter().any(ChannelWrapper::is_pubmed_item)
+    }
+
+    pub fn is_pubmed_item(item: &Item) -> bool {
         item.guid()
-            .as_ref()
-            .unwrap()
+            .is_some_and(|guid| guid.value().starts_with("pubmed:"))
+    }
+
+    pub fn parse_guid(item: &Item) -> Option<u32> {
+        item.guid()?
             .value()
             .trim_start_matches("pubmed:")
             .parse()
+            .ok()
+    }
+
+    /// The items with their id. In PubMed feeds the items without a valid PMID are left out.
+    pub fn item_ids(&self) -> Vec<(&Item, u32)> {
+        let is_pubmed = self.is_pubmed();
+        self.items()
+            .iter()
+            .filter_map(|item| {
+                let id = if is_pubmed {
+                    ChannelWrapper::parse_guid(item)
+                } else {
+                    ChannelWrapper::item_id(item)
+                };
+                id.map(|id| (item, id))
+            })
+            .collect()
+    }
+
+    /// Identifies an item: the PMID for PubMed items, otherwise a hash of the
+    /// guid, link or title.
+    pub fn item_id(item: &Item) -> Option<u32> {
+        if let Some(pmid) = ChannelWrapper::parse_guid(item) {
+            return Some(pmid);
+        }
+        let key = item
+            .guid()
+            .map(|guid| guid.value())
+            .or(item.link())
+            .or(item.title())?;
+        Some(fnv1a_hash(key.trim()))
+    }
+
+    /// Reads an RSS channel or an Atom feed.
+    pub fn read_from(content: &[u8]) -> Result<ChannelWrapper, Box<dyn Error + Sync + Send>> {
+        match Channel::read_from(content) {
+            Ok(channel) => Ok(ChannelWrapper(channel)),
+            Err(rss_error) => match atom_syndication::Feed::read_from(content) {
+                Ok(feed) => Ok(ChannelWrapper::from_atom(&feed)),
+                Err(atom_error) => Err(format!(
+                    "Not a valid RSS ({rss_error}) or Atom ({atom_error}) feed."
+                )
+                .into()),
+            },
+        }
+    }
+
+    /// Converts an Atom feed to an RSS channel, with the authors as Dublin Core creators.
+    pub fn from_atom(feed: &atom_syndication::Feed) -> ChannelWrapper {
+        let alternate_link = |links: &[atom_syndication::Link]| {
+            links
+                .iter()
+                .find(|link| link.rel() == "alternate")
+                .or(links.first())
+                .map(|link| link.href().to_string())
+        };
+        let items = feed
+            .entries()
+            .iter()
+            .map(|entry| {
+                let dublin_core = DublinCoreExtension {
+                    creators: entry
+                        .authors()
+                        .iter()
+                        .map(|a| a.name().to_string())
+                        .collect(),
+                    ..Default::default()
+                };
+                ItemBuilder::default()
+                    .title(Some(entry.title().value.clone()))
+                    .link(alternate_link(entry.links()))
+                    .guid(Some(
+                        GuidBuilder::default()
+                            .value(entry.id().to_string())
+                            .permalink(false)
+                            .build(),
+                    ))
+                    .pub_date(Some(
+                        entry.published().unwrap_or(entry.updated()).to_rfc2822(),
+                    ))
+                    .description(entry.summary().map(|summary| summary.value.clone()))
+                    .content(
+                        entry
+                            .content()
+                            .and_then(|content| content.value())
+                            .map(|content| content.to_string()),
+                    )
+                    .dublin_core_ext(Some(dublin_core))
+                    .build()
+            })
+            .collect::<Vec<Item>>();
+        let channel = ChannelBuilder::default()
+            .title(feed.title().value.clone())
+            .link(alternate_link(feed.links()).unwrap_or_default())
+            .last_build_date(Some(feed.updated().to_rfc2822()))
+            .items(items)
+            .build();
+        ChannelWrapper(channel)
     }
 }
 
+/// 32 bit FNV-1a: a stable hash, so the ids stay the same between runs.
+fn fnv1a_hash(text: &str) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in text.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
 // impl Serialize for ChannelWrapper {
 //     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 //     where
@@ -122,4 +279,112 @@ mod tests {
         let cw2 = serde_json::from_str(&json).unwrap();
         assert_eq!(cw, cw2)
     }
+
+    #[test]
+    fn malformed_items() {
+        let item = |guid: Option<&str>, pub_date: &str| {
+            rss::ItemBuilder::default()
+                .title(Some("Title".to_string()))
+                .guid(guid.map(|g| rss::GuidBuilder::default().value(g.to_string()).build()))
+                .pub_date(Some(pub_date.to_string()))
+                .build()
+        };
+        let channel = rss::ChannelBuilder::default()
+            .items(vec![
+                item(Some("pubmed:30"), "Tue, 15 Apr 2025 06:00:00 -0400"),
+                item(None, "Tue, 15 Apr 2025 06:00:00 -0400"),
+                item(Some("pubmed:abc"), "15/04/2025"),
+                item(Some("pubmed:20"), "Mon, 14 Apr 2025 06:00:00 -0400"),
+                item(Some("pubmed:10"), "Sun, 13 Apr 2025 06:00:00 -0400"),
+            ])
+            .build();
+        let cw = ChannelWrapper::build(channel);
+
+        assert_eq!(cw.malformed_items().len(), 2);
+        let new_items = cw.get_unseen_items(&HashSet::from([10]));
+        assert_eq!(new_items.len(), 2);
+        assert_eq!(ChannelWrapper::parse_guid(new_items[1]), Some(20));
+        let new_items = cw.get_new_items("Sun, 13 Apr 2025 12:00:00 -0400").unwrap();
+        assert_eq!(new_items.len(), 3);
+    }
+
+    #[test]
+    fn unseen_items() {
+        let item = |guid: &str| {
+            rss::ItemBuilder::default()
+                .guid(Some(
+                    rss::GuidBuilder::default().value(guid.to_string()).build(),
+                ))
+                .build()
+        };
+        // An older PMID that was newly indexed, between newer ones
+        let channel = rss::ChannelBuilder::default()
+            .items(vec![
+                item("pubmed:40"),
+                item("pubmed:30"),
+                item("pubmed:5"),
+                item("pubmed:20"),
+            ])
+            .build();
+        let cw = ChannelWrapper::build(channel);
+        let new_items = cw.get_unseen_items(&HashSet::from([30, 20, 10]));
+        let ids: Vec<Option<u32>> = new_items
+            .iter()
+            .map(|item| ChannelWrapper::parse_guid(item))
+            .collect();
+        assert_eq!(ids, vec![Some(40), Some(5)]);
+        assert!(
+            cw.get_unseen_items(&HashSet::from([40, 30, 20, 5]))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn atom_feed() {
+        let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Example Journal</title>
+  <link href="https://journal.example.org/"/>
+  <updated>2025-04-15T10:00:00Z</updated>
+  <id>urn:uuid:60a76c80-d399-11d9-b93c-0003939e0af6</id>
+  <entry>
+    <title>Second article</title>
+    <link rel="alternate" href="https://journal.example.org/2"/>
+    <id>https://journal.example.org/2</id>
+    <updated>2025-04-15T09:00:00Z</updated>
+    <author><name>Lili Zhao</name></author>
+    <summary>A summary.</summary>
+  </entry>
+  <entry>
+    <title>First article</title>
+    <link href="https://journal.example.org/1"/>
+    <id>https://journal.example.org/1</id>
+    <published>2025-04-14T09:00:00Z</published>
+    <updated>2025-04-14T12:00:00Z</updated>
+  </entry>
+</feed>"#;
+        let cw = ChannelWrapper::read_from(atom.as_bytes()).unwrap();
+        assert_eq!(cw.title(), "Example Journal");
+        assert_eq!(cw.items().len(), 2);
+        let item = &cw.items()[0];
+        assert_eq!(item.link(), Some("https://journal.example.org/2"));
+        assert_eq!(item.description(), Some("A summary."));
+        assert_eq!(
+            item.dublin_core_ext().unwrap().creators(),
+            ["Lili Zhao".to_string()]
+        );
+        assert_eq!(
+            cw.items()[1].pub_date(),
+            Some("Mon, 14 Apr 2025 09:00:00 +0000")
+        );
+        assert!(cw.malformed_items().is_empty());
+
+        // Without PMIDs the items are identified by a hash of their id
+        let oldest = ChannelWrapper::item_id(&cw.items()[1]).unwrap();
+        let new_items = cw.get_unseen_items(&HashSet::from([oldest]));
+        assert_eq!(new_items.len(), 1);
+        assert_eq!(new_items[0].title(), Some("Second article"));
+
+        assert!(ChannelWrapper::read_from(b"<html></html>").is_err());
+    }
 }
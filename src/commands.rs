@@ -1,12 +1,29 @@
-use crate::datastructs::{ChannelLookupTable, ItemMetadata, PubmedFeed, User, UserRssList};
-use crate::formatter::PreppedMessage;
+use crate::archive::SEARCH_PAGE_SIZE;
+use crate::channelwrapper::ChannelWrapper;
+use crate::datastructs::{ChannelLookupTable, ItemMetadata, PubmedFeed, User, UserRssList, Visibility};
+use crate::export::{ExportFormat, export};
+use crate::formatter::{MessageFormat, MessageStyle, PreppedMessage};
+use crate::health::health_report;
+use crate::journals::{MAX_FOUND_JOURNALS, journal_catalogue};
+use crate::links::{find_link, link_templates, select_links};
+use crate::opml::{OpmlFeed, parse_opml, to_opml};
 use crate::preset::{self, Keywords, Preset, available_presets};
 use crate::{CustomResult, db};
-use chrono::NaiveDate;
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use rss::Item;
 use rusqlite::Connection;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
 use teloxide::types::ParseMode;
 use teloxide::utils::command::{BotCommands, ParseError};
 
+/// The most articles sent for one /since.
+const MAX_SINCE_ITEMS: usize = 50;
+/// The most articles sent when feeds are added, see `User::backlog`.
+const MAX_BACKLOG: u32 = 20;
+
 #[derive(BotCommands, PartialEq, Debug, Clone)]
 #[command(
     rename_rule = "lowercase",
@@ -35,10 +52,15 @@ pub enum Command {
     )]
     DeleteCollection { collection_index: usize },
     #[command(
-        description = "[feed_name] [link] - Add a new pubmed feed. Provide the name of the feed (with any spaces replaced by _) and link.",
+        description = "[feed_name] [link] - Add a new pubmed, RSS or Atom feed. Provide the name of the feed (with any spaces replaced by _) and link.",
         parse_with = "split"
     )]
     NewFeed { name: String, link: String },
+    #[command(
+        description = "[feed_name] [search term] - Add a new feed from a PubMed search, eg. /newsearch endometriosis endometriosis[mesh] AND mri. Includes all authors, MeSH terms and publication types.",
+        parse_with = search_parser
+    )]
+    NewSearch { name: String, query: String },
     #[command(
         description = "[feed id] [collection] - Add a feed. Provide the id and collection number. Eg. /addfeed 101532453 0 to add Insights in Imaging to your first feed collection.",
         parse_with = "split"
@@ -99,13 +121,129 @@ pub enum Command {
         preset: String,
         collection_index: usize,
     },
+    #[command(
+        description = "[style] [collection] - Set how much of an article is sent: compact (title, journal and links), standard (start of the abstract) or full (complete abstract).",
+        parse_with = "split"
+    )]
+    SetStyle {
+        style: String,
+        collection_index: usize,
+    },
+    #[command(
+        description = "[collection] [template] - Use your own message template. Available placeholders: {title}, {journal}, {authors}, {date}, {doi}, {pmid}, {abstract} and {links}. Eg. /settemplate 0 {title} ({journal}) {links}",
+        parse_with = template_parser
+    )]
+    SetTemplate {
+        collection_index: usize,
+        template: String,
+    },
+    #[command(description = "List the available links and the ones shown below your articles.")]
+    Links,
+    #[command(
+        description = "[link] [link] ... - Pick the links shown below your articles, eg. /setlinks Library PubMed. Use /setlinks default to go back to the default links.",
+        parse_with = links_parser
+    )]
+    SetLinks { links: Vec<String> },
+    #[command(
+        description = "[format] [collection] - Export the recent articles of a collection as a file for Zotero or EndNote. Formats: ris, bibtex or csljson.",
+        parse_with = "split"
+    )]
+    Export {
+        format: String,
+        collection_index: usize,
+    },
+    #[command(
+        description = "[format] [pmid] - Export one article as a file. Formats: ris, bibtex or csljson.",
+        parse_with = "split"
+    )]
+    ExportArticle { format: String, pmid: u32 },
+    #[command(
+        description = "Export the feeds of your collections as an OPML file, to import them in an RSS reader.",
+        parse_with = "split"
+    )]
+    ExportOpml,
+    #[command(
+        description = "[search] [page] - Search the titles and abstracts of all articles sent before. Put \"phrases\" between quotes. Add a page number for more results, eg. /search adrenal vein sampling 2",
+        parse_with = search_page_parser
+    )]
+    Search { query: String, page: usize },
+    #[command(
+        description = "[YYYY-MM-DD] [collection] - Send the articles downloaded since the date that match a collection, or all your collections if no collection is given. Eg. /since 2025-03-01 0",
+        parse_with = since_parser
+    )]
+    Since {
+        date: String,
+        collection_index: Option<usize>,
+    },
+    #[command(
+        description = "[number] - Set how many of the latest matching articles are sent right away when you add a feed or a journal preset to a collection. 0 to send none.",
+        parse_with = "split"
+    )]
+    SetBacklog { backlog: u32 },
+    #[command(
+        description = "[feed_id] [visibility] - Set who can find a feed you added: private (only you) or shared (anyone with the id). Ask an admin to make it public.",
+        parse_with = "split"
+    )]
+    SetVisibility { feed_id: u32, visibility: String },
+    #[command(
+        description = "[name] - Find a journal by its name, abbreviation or ISSN, eg. /findjournal european radiology",
+        parse_with = journal_name_parser
+    )]
+    FindJournal { name: String },
+    #[command(
+        description = "[nlm_id] [collection] - Add the PubMed feed of a journal found with /findjournal to a collection, eg. /addjournal 9114774 0",
+        parse_with = "split"
+    )]
+    AddJournal {
+        nlm_id: String,
+        collection_index: usize,
+    },
+}
+
+/// The answer to a command: a message, a file for exports, a message
+/// followed by articles that are sent like the new items of the feeds, or a
+/// message with messages for other users.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Reply {
+    Text(String),
+    Document {
+        file_name: String,
+        content: String,
+        caption: String,
+    },
+    Items {
+        text: String,
+        items: Vec<(Item, ItemMetadata)>,
+    },
+    Notify {
+        text: String,
+        /// The chat ids and the messages for them.
+        messages: Vec<(i64, String)>,
+    },
+}
+
+impl From<String> for Reply {
+    fn from(text: String) -> Self {
+        Reply::Text(text)
+    }
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reply::Text(text) => write!(f, "{}", text),
+            Reply::Document { content, .. } => write!(f, "{}", content),
+            Reply::Items { text, .. } => write!(f, "{}", text),
+            Reply::Notify { text, .. } => write!(f, "{}", text),
+        }
+    }
 }
 
 pub async fn user_command_handler(
     msg: &str,
     user: &mut User,
     conn: &rusqlite::Connection,
-) -> CustomResult<String> {
+) -> CustomResult<Reply> {
     let command = Command::parse(msg, "");
     if command.is_err() {
         return Err(format!(
@@ -115,14 +253,22 @@ pub async fn user_command_handler(
         .into());
     }
 
-    match command.unwrap() {
+    let answer = match command.unwrap() {
+        Command::Export { format, collection_index } => return export_collection(conn, user, format, collection_index),
+        Command::ExportArticle { format, pmid } => return export_article(conn, format, pmid),
+        Command::ExportOpml => return export_opml(conn, user),
+        Command::Since { date, collection_index } => return since(conn, user, date, collection_index),
         Command::Start => Ok("Welcome to the telegram pubmed notifier bot! Send /help for a list of available commands.".to_string()),
         Command::Help => Ok(Command::descriptions().to_string()),
         Command::Collections => Ok(format!("You currently have {} collections in total. Inspect them with /collection [num] (starting at 0).", user.rss_lists.len())) ,
         Command::Collection { collection_index  } => show_collection(conn, user, collection_index),
-        Command::Feeds => list_feeds(conn),
-        Command::NewFeed { name, link } =>  newfeed(conn, name, link).await,
-        Command::AddFeed { feed_id, collection_index } => add_feed_to_collection(conn, user, feed_id, collection_index),
+        Command::Feeds => list_feeds(conn, user),
+        Command::NewFeed { name, link } =>  newfeed(conn, user, name, link).await,
+        Command::NewSearch { name, query } =>  newsearch(conn, user, name, query).await,
+        Command::SetVisibility { feed_id, visibility } => set_own_visibility(conn, user, feed_id, visibility),
+        Command::AddFeed { feed_id, collection_index } => return add_feed_to_collection(conn, user, feed_id, collection_index),
+        Command::AddJournal { nlm_id, collection_index } => return add_journal(conn, user, nlm_id, collection_index).await,
+        Command::FindJournal { name } => find_journal(&name),
         Command::AddToWhitelist { keyword, collection_index } => add_to_whitelist(conn, user, keyword, collection_index),
         Command::AddToBlacklist { keyword, collection_index } => add_to_blacklist(conn, user, keyword, collection_index),
         Command::RemoveFeed { feed_id, collection_index } => remove_feed_from_collection(conn, user, feed_id, collection_index),
@@ -132,8 +278,15 @@ pub async fn user_command_handler(
         Command::DeleteCollection { collection_index } => delete_collection(conn, user, collection_index),
         Command::Presets => show_presets(),
         Command::Preset {preset} => show_preset_content(conn, &preset),
-        Command::AddPresetToCollection { preset, collection_index} => add_preset_to_collection(conn, user, preset, collection_index),
-    }
+        Command::AddPresetToCollection { preset, collection_index} => return add_preset_to_collection(conn, user, preset, collection_index),
+        Command::SetBacklog { backlog } => set_backlog(conn, user, backlog),
+        Command::SetStyle { style, collection_index } => set_style(conn, user, style, collection_index),
+        Command::SetTemplate { collection_index, template } => set_template(conn, user, template, collection_index),
+        Command::Links => list_links(user),
+        Command::SetLinks { links } => set_links(conn, user, links),
+        Command::Search { query, page } => search(conn, user, query, page),
+    };
+    answer.map(Reply::Text)
 }
 
 fn get_users(conn: &Connection) -> CustomResult<String> {
@@ -151,11 +304,17 @@ async fn as_user(conn: &Connection, user_id: i64, msg: &str) -> CustomResult<Str
         return Ok("User does not exist.".to_string());
     }
     // Unwrap is handled by the if statement above.
-    return user_command_handler(msg, other_user.as_mut().unwrap(), conn).await;
+    return user_command_handler(msg, other_user.as_mut().unwrap(), conn)
+        .await
+        .map(|reply| reply.to_string());
 }
 
-fn list_feeds(conn: &Connection) -> CustomResult<String> {
-    let feeds = db::sqlite::get_feeds(conn)?;
+/// The public feeds and the feeds of the user.
+fn list_feeds(conn: &Connection, user: &User) -> CustomResult<String> {
+    let feeds: Vec<PubmedFeed> = db::sqlite::get_feeds(conn)?
+        .into_iter()
+        .filter(|feed| feed.listed_for(user.chat_id))
+        .collect();
     Ok(ChannelLookupTable::from_vec(feeds)?.format())
 }
 
@@ -164,24 +323,122 @@ fn add_feed_to_collection(
     user: &mut User,
     feed_id: u32,
     collection_index: usize,
-) -> CustomResult<String> {
+) -> CustomResult<Reply> {
     // Get userCollection, if it exists
     if let Some(coll) = user.rss_lists.get_mut(collection_index) {
         // Check that the feed_id actually corresponds with an existing channel.
-        if let Some(feed) = db::sqlite::get_feed(conn, feed_id)? {
-            coll.feeds.insert(feed_id);
+        if let Some(feed) = db::sqlite::get_feed(conn, feed_id)?
+            && feed.visible_to(user.chat_id)
+        {
+            let added = coll.feeds.insert(feed_id);
             db::sqlite::update_user(conn, user)?;
             db::sqlite::add_subscriber(conn, feed_id, 1)?;
-            return Ok(format!(
+            let text = format!(
                 "Added {} ({}) to collection {}.",
                 feed_id, feed.name, collection_index
-            ));
+            );
+            let mut backlog = Vec::new();
+            if added {
+                backlog = backlog_items(conn, user, collection_index, &[feed_id])?;
+            }
+            return Ok(with_backlog(text, backlog));
         }
-        return Ok(format!("Feed {} does not exist.", feed_id));
+        return Ok(format!("Feed {} does not exist.", feed_id).into());
     }
     Ok(format!(
         "The index is out of range: pick a number between 0 and {}, or create a new collection with /newcollection",
         user.rss_lists.len().saturating_sub(1)
+    )
+    .into())
+}
+
+/// The latest items in the downloaded channels of the feeds that match the
+/// collection, oldest first, at most `User::backlog`. They are only sent to
+/// this user: the seen items of the feeds do not change. A feed that was never
+/// downloaded has no backlog, which is why new feeds are downloaded before
+/// they are added (see `add_private_feed` and `add_journal_feed`).
+fn backlog_items(
+    conn: &Connection,
+    user: &User,
+    collection_index: usize,
+    feed_ids: &[u32],
+) -> CustomResult<Vec<(Item, ItemMetadata)>> {
+    let Some(collection) = user.rss_lists.get(collection_index) else {
+        return Ok(Vec::new());
+    };
+    if user.backlog == 0 {
+        return Ok(Vec::new());
+    }
+    let mut ids = HashSet::new();
+    let mut candidates = Vec::new();
+    for feed_id in feed_ids {
+        let Some(feed) = db::sqlite::get_feed(conn, *feed_id)? else {
+            continue;
+        };
+        for (item, id) in feed.channel.item_ids() {
+            if !ids.insert(id) {
+                continue;
+            }
+            if let Some(matched) = collection.matched_keywords(item) {
+                let pub_date = item.pub_date().and_then(|date| DateTime::parse_from_rfc2822(date).ok());
+                let item_metadata = ItemMetadata {
+                    collection: Some(collection_index),
+                    style: collection.style.clone(),
+                    feed_id: Some(*feed_id),
+                    links: user.links.clone(),
+                    highlights: matched,
+                    ..Default::default()
+                };
+                candidates.push((pub_date, item.clone(), item_metadata));
+            }
+        }
+    }
+    // Newest first, the items without a date last
+    candidates.sort_by_key(|(pub_date, _, _)| Reverse(*pub_date));
+    candidates.truncate(user.backlog as usize);
+    Ok(candidates
+        .into_iter()
+        .rev()
+        .map(|(_, item, item_metadata)| (item, item_metadata))
+        .collect())
+}
+
+fn with_backlog(text: String, backlog: Vec<(Item, ItemMetadata)>) -> Reply {
+    if backlog.is_empty() {
+        return Reply::Text(text);
+    }
+    Reply::Items {
+        text: format!("{}\nThe latest {} matching articles:", text, backlog.len()),
+        items: backlog,
+    }
+}
+
+fn set_own_visibility(conn: &Connection, user: &User, feed_id: u32, visibility: String) -> CustomResult<String> {
+    let visibility = match Visibility::from_str(&visibility) {
+        Ok(Visibility::Public) => return Ok("Only an admin can make a feed public.".to_string()),
+        Ok(visibility) => visibility,
+        Err(_) => return Ok(format!("'{}' is not a valid visibility! Pick private or shared.", visibility)),
+    };
+    let Some(mut feed) = db::sqlite::get_feed(conn, feed_id)?.filter(|feed| feed.owner == Some(user.chat_id)) else {
+        return Ok(format!("You did not add a feed with id {}.", feed_id));
+    };
+    if feed.visibility == Visibility::Public {
+        return Ok(format!("Feed {} is public: only an admin can change it.", feed_id));
+    }
+    feed.visibility = visibility;
+    db::sqlite::update_feed(conn, &feed)?;
+    Ok(format!("Feed {} ({}) is now {}.", feed_id, feed.name, visibility))
+}
+
+fn set_backlog(conn: &Connection, user: &mut User, backlog: u32) -> CustomResult<String> {
+    user.backlog = backlog.min(MAX_BACKLOG);
+    db::sqlite::update_user(conn, user)?;
+    if user.backlog == 0 {
+        return Ok("No articles will be sent when you add feeds.".to_string());
+    }
+    Ok(format!(
+        "The latest {} matching articles will be sent when you add feeds.",
+        user.backlog
     ))
 }
 fn remove_feed_from_collection(
@@ -323,17 +580,110 @@ fn show_collection(
     }
 }
 
-async fn newfeed(conn: &Connection, name: String, link: String) -> CustomResult<String> {
-    let mut feed = PubmedFeed::build_from_link(&link, &name)?;
-    feed.update_channel_limited().await?;
-    feed.update_guid();
+/// Only the items published after the feed was added are sent.
+fn mark_current_items_seen(conn: &Connection, uid: u32, feed: &PubmedFeed) -> CustomResult<()> {
+    let ids: Vec<u32> = feed.channel.item_ids().iter().map(|(_, id)| *id).collect();
+    db::sqlite::mark_seen(conn, uid, &ids, Local::now().timestamp())?;
+    Ok(())
+}
+
+async fn newfeed(conn: &Connection, user: &User, name: String, link: String) -> CustomResult<String> {
+    let feed = PubmedFeed::build_from_link(&link, &name)?;
+    add_private_feed(conn, user, feed, "feed").await
+}
+
+async fn newsearch(conn: &Connection, user: &User, name: String, query: String) -> CustomResult<String> {
+    let feed = PubmedFeed::build_from_query(&query, &name)?;
+    add_private_feed(conn, user, feed, "search").await
+}
+
+/// Adds the feed as a private feed of the user, or points to the feed with the same link.
+async fn add_private_feed(conn: &Connection, user: &User, mut feed: PubmedFeed, kind: &str) -> CustomResult<String> {
+    if let Some(existing) = db::sqlite::get_feeds(conn)?.into_iter().find(|existing| existing.link == feed.link) {
+        let uid = existing.uid.unwrap_or_default();
+        if !existing.visible_to(user.chat_id) {
+            return Ok(format!("This {} can not be added. Ask an admin for help.", kind));
+        }
+        return Ok(format!(
+            "This {} already exists: {}, with id {}. Add it to a collection with /addfeed {} [collection_index].",
+            kind, existing.name, uid, uid
+        ));
+    }
+    feed.owner = Some(user.chat_id);
+    feed.visibility = Visibility::Private;
+    if let Err(e) = feed.update_channel().await {
+        return Ok(format!("Could not download the {} {}: {}", kind, feed.name, e));
+    }
     let uid = db::sqlite::add_feed(conn, &feed)?;
+    mark_current_items_seen(conn, uid, &feed)?;
     Ok(format!(
-        "Added feed {}, with id {}. Add it to a collection with /addfeed {} [collection_index].",
-        name, uid, uid
+        "Added {} {}, with id {}. Add it to a collection with /addfeed {} [collection_index]. Only you can see it: share it with others with /setvisibility {} shared.",
+        kind, feed.name, uid, uid, uid
     ))
 }
 
+fn find_journal(name: &str) -> CustomResult<String> {
+    let catalogue = journal_catalogue();
+    if catalogue.journals.is_empty() {
+        return Ok("There is no journal catalogue yet. Ask an admin to add one.".to_string());
+    }
+    let found = catalogue.find(name);
+    if found.is_empty() {
+        return Ok(format!("No journals found for '{}'.", name));
+    }
+    let mut r = format!("Journals for '{}':\n", name);
+    for journal in found.iter().take(MAX_FOUND_JOURNALS) {
+        match journal.med_abbr.as_deref() {
+            Some(abbr) if abbr != journal.title => {
+                r.push_str(&format!("{} - {} ({})\n", journal.nlm_id, journal.title, abbr))
+            }
+            _ => r.push_str(&format!("{} - {}\n", journal.nlm_id, journal.title)),
+        }
+    }
+    if found.len() > MAX_FOUND_JOURNALS {
+        r.push_str(&format!("... and {} more, try a longer name.\n", found.len() - MAX_FOUND_JOURNALS));
+    }
+    r.push_str("\nAdd a journal to a collection with /addjournal [nlm_id] [collection_index].");
+    Ok(r)
+}
+
+/// Adds the PubMed feed of a journal in the catalogue, unless it already
+/// exists, and adds it to the collection.
+async fn add_journal(conn: &Connection, user: &mut User, nlm_id: String, collection_index: usize) -> CustomResult<Reply> {
+    let Some(journal) = journal_catalogue().get(&nlm_id) else {
+        return Ok(format!("There is no journal with NLM ID {}. Find it with /findjournal [name].", nlm_id).into());
+    };
+    if collection_index >= user.rss_lists.len() {
+        return Ok(format!(
+            "The index is out of range: pick a number between 0 and {}, or create a new collection with /newcollection",
+            user.rss_lists.len().saturating_sub(1)
+        )
+        .into());
+    }
+    let feed = PubmedFeed::build_from_link(&journal.rss_link(), &journal.title)?;
+    add_journal_feed(conn, user, feed, collection_index).await
+}
+
+/// Adds the journal feed to the collection. A new feed is downloaded first,
+/// for the backlog and to find a dead link right away.
+async fn add_journal_feed(conn: &Connection, user: &mut User, mut feed: PubmedFeed, collection_index: usize) -> CustomResult<Reply> {
+    let existing = db::sqlite::get_feeds(conn)?.into_iter().find(|existing| {
+        existing.link == feed.link || (feed.uid.is_some() && existing.uid == feed.uid)
+    });
+    let feed_id = match existing {
+        Some(existing) => existing.uid.unwrap_or_default(),
+        None => {
+            if let Err(e) = feed.update_channel().await {
+                return Ok(format!("Could not download the feed of {}: {}", feed.name, e).into());
+            }
+            let uid = db::sqlite::add_feed(conn, &feed)?;
+            mark_current_items_seen(conn, uid, &feed)?;
+            uid
+        }
+    };
+    add_feed_to_collection(conn, user, feed_id, collection_index)
+}
+
 fn new_collection(conn: &Connection, user: &mut User) -> CustomResult<String> {
     let mut collection = UserRssList::new();
     collection.blacklist =
@@ -397,12 +747,15 @@ fn add_preset_to_collection(
     user: &mut User,
     preset: String,
     collection_index: usize,
-) -> CustomResult<String> {
+) -> CustomResult<Reply> {
+    let mut added_feeds = Vec::new();
     if let Some(collection) = user.rss_lists.get_mut(collection_index) {
         match preset::parse_preset(&preset) {
             Some(preset) => match preset {
                 Preset::Journal(p) => {
-                    collection.feeds = preset::merge_journal_preset_with_set(p, &collection.feeds);
+                    let feeds = preset::merge_journal_preset_with_set(p, &collection.feeds);
+                    added_feeds = feeds.difference(&collection.feeds).copied().collect();
+                    collection.feeds = feeds;
                     // TODO: a more elegant solution to update this!
                     db::sqlite::update_subscribers(conn)?;
                 }
@@ -417,12 +770,40 @@ fn add_preset_to_collection(
                     }
                 },
             },
-            None => return Ok(format!("'{}' is not a valid preset!", preset)),
+            None => return Ok(format!("'{}' is not a valid preset!", preset).into()),
+        }
+        db::sqlite::update_user(conn, user)?;
+        let text = format!("Added preset {} to collection {}", preset, collection_index);
+        let backlog = backlog_items(conn, user, collection_index, &added_feeds)?;
+        return Ok(with_backlog(text, backlog));
+    }
+    Ok(format!(
+        "The index is out of range: pick a number between 0 and {}",
+        user.rss_lists.len().saturating_sub(1)
+    )
+    .into())
+}
+
+fn set_style(
+    conn: &Connection,
+    user: &mut User,
+    style: String,
+    collection_index: usize,
+) -> CustomResult<String> {
+    if let Some(collection) = user.rss_lists.get_mut(collection_index) {
+        match MessageStyle::from_str(&style) {
+            Ok(style) => collection.style = style,
+            Err(_) => {
+                return Ok(format!(
+                    "'{}' is not a valid style! Pick compact, standard or full.",
+                    style
+                ));
+            }
         }
         db::sqlite::update_user(conn, user)?;
         return Ok(format!(
-            "Added preset {} to collection {}",
-            preset, collection_index
+            "Changed the style of collection {} to {}.",
+            collection_index, style
         ));
     }
     Ok(format!(
@@ -431,35 +812,92 @@ fn add_preset_to_collection(
     ))
 }
 
-fn _set_last_update(conn: &Connection, user: &mut User, date: String) -> CustomResult<String> {
-    let newdate = NaiveDate::parse_from_str(&date, "%Y-%m-%d")?
-        .and_hms_opt(0, 0, 0)
-        .unwrap()
-        .and_utc();
-    user.last_pushed = newdate.to_rfc2822();
-    db::sqlite::update_user(conn, user)?;
-    Ok(format!("Changed the last updated time to {}", newdate))
-}
-
-fn _get_new_since(conn: &Connection, user: &User, date: String) -> CustomResult<String> {
-    let mut tempuser = user.clone();
-    let newdate = NaiveDate::parse_from_str(&date, "%Y-%m-%d")?
-        .and_hms_opt(0, 0, 0)
-        .unwrap()
-        .and_utc();
-    tempuser.last_pushed = newdate.to_rfc2822();
-
-    let feeds = ChannelLookupTable::from_vec(db::sqlite::get_feeds(conn)?)?;
-    if let Some(items) = tempuser.get_new_items(&feeds) {
-        for item in items {
-            println!("----------------------------");
-            println!(
-                "{}",
-                PreppedMessage::build(item, &ItemMetadata::default()).format(ParseMode::MarkdownV2)
-            );
+fn set_template(
+    conn: &Connection,
+    user: &mut User,
+    template: String,
+    collection_index: usize,
+) -> CustomResult<String> {
+    if let Some(collection) = user.rss_lists.get_mut(collection_index) {
+        collection.style = MessageStyle::Template(template);
+        db::sqlite::update_user(conn, user)?;
+        return Ok(format!(
+            "Collection {} now uses your template. Go back with /setstyle full {}.",
+            collection_index, collection_index
+        ));
+    }
+    Ok(format!(
+        "The index is out of range: pick a number between 0 and {}",
+        user.rss_lists.len().saturating_sub(1)
+    ))
+}
+
+/// Sends the stored articles since the date that match the collection, or all
+/// collections. An article in several feeds or collections is sent once.
+fn since(
+    conn: &Connection,
+    user: &User,
+    date: String,
+    collection_index: Option<usize>,
+) -> CustomResult<Reply> {
+    let Some(since) = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+    else {
+        return Ok(format!("'{}' is not a valid date! Use YYYY-MM-DD, eg. /since 2025-03-01", date).into());
+    };
+    let collections: Vec<(usize, &UserRssList)> = match collection_index {
+        Some(index) => match user.rss_lists.get(index) {
+            Some(collection) => vec![(index, collection)],
+            None => {
+                return Ok(format!(
+                    "The index is out of range: pick a number between 0 and {}",
+                    user.rss_lists.len().saturating_sub(1)
+                )
+                .into());
+            }
+        },
+        None => user.rss_lists.iter().enumerate().collect(),
+    };
+
+    let articles = db::sqlite::get_articles_since(conn, since.timestamp())?;
+    let mut sent = HashSet::new();
+    let mut items = Vec::new();
+    for (index, collection) in collections {
+        for (feed_id, article) in articles.iter() {
+            if !collection.feeds.contains(feed_id) || sent.contains(&article.key) {
+                continue;
+            }
+            let item = article.to_item();
+            if let Some(matched) = collection.matched_keywords(&item) {
+                sent.insert(article.key.clone());
+                let item_metadata = ItemMetadata {
+                    collection: Some(index),
+                    style: collection.style.clone(),
+                    feed_id: Some(*feed_id),
+                    links: user.links.clone(),
+                    highlights: matched,
+                    ..Default::default()
+                };
+                items.push((item, item_metadata));
+            }
         }
     }
-    Ok("Output to sdt...".to_string())
+    if items.is_empty() {
+        return Ok(format!("No matching articles since {}.", date).into());
+    }
+    let total = items.len();
+    let text = if total > MAX_SINCE_ITEMS {
+        items.truncate(MAX_SINCE_ITEMS);
+        format!(
+            "Found {} articles since {}. Sending the first {}: use a later date for the others.",
+            total, date, MAX_SINCE_ITEMS
+        )
+    } else {
+        format!("Sending {} articles since {}.", total, date)
+    };
+    Ok(Reply::Items { text, items })
 }
 
 #[derive(BotCommands, Clone)]
@@ -477,9 +915,40 @@ pub enum AdminCommand {
     CleanUsers,
     #[command(description = "[feed_id] [item_index] - Get an item from a feed.")]
     GetItem { feed_id: u32, index: usize },
+    #[command(description = "List the stale, empty and failing feeds.")]
+    FeedHealth,
+    #[command(
+        description = "[feed_id] [minutes] - Set how often a feed is downloaded. 0 to learn it from how often new items appear."
+    )]
+    SetInterval { feed_id: u32, minutes: u32 },
+    #[command(description = "[feed_id] [name] - Rename a feed.", parse_with = rename_parser)]
+    RenameFeed { feed_id: u32, name: String },
+    #[command(description = "[feed_id] [link] - Change the link of a feed.")]
+    SetFeedLink { feed_id: u32, link: String },
+    #[command(
+        description = "[feed_id] - Delete a feed. It is removed from all collections and the subscribers are notified. Feeds of the journal presets can not be deleted."
+    )]
+    DeleteFeed { feed_id: u32 },
+    #[command(
+        description = "[feed_id] [into_feed_id] - Merge a duplicate feed into another one. The collections get the other feed instead. Feeds of the journal presets can not be merged."
+    )]
+    MergeFeeds { feed_id: u32, into_feed_id: u32 },
+    #[command(
+        description = "[feed_id] [visibility] - Set who can find a feed: private (only the owner), shared (anyone with the id) or public (listed in /feeds)."
+    )]
+    SetFeedVisibility { feed_id: u32, visibility: String },
+    #[command(
+        description = "Add the feeds of an OPML file as public feeds. Send the file with /importopml as the caption."
+    )]
+    ImportOpml,
 }
 
-pub async fn admin_command_handler(msg: &str, conn: &rusqlite::Connection) -> CustomResult<String> {
+/// `document` is the content of the file sent with the command, if any.
+pub async fn admin_command_handler(
+    msg: &str,
+    document: Option<&str>,
+    conn: &rusqlite::Connection,
+) -> CustomResult<Reply> {
     let command = AdminCommand::parse(msg, "");
     if command.is_err() {
         return Err(format!(
@@ -488,17 +957,217 @@ pub async fn admin_command_handler(msg: &str, conn: &rusqlite::Connection) -> Cu
         )
         .into());
     }
-    match command.unwrap() {
+    let answer = match command.unwrap() {
+        AdminCommand::DeleteFeed { feed_id } => return delete_feed(conn, feed_id),
+        AdminCommand::RenameFeed { feed_id, name } => rename_feed(conn, feed_id, name),
+        AdminCommand::SetFeedLink { feed_id, link } => set_feed_link(conn, feed_id, link),
+        AdminCommand::MergeFeeds { feed_id, into_feed_id } => merge_feeds(conn, feed_id, into_feed_id),
+        AdminCommand::SetFeedVisibility { feed_id, visibility } => set_feed_visibility(conn, feed_id, visibility),
+        AdminCommand::ImportOpml => match document {
+            Some(document) => import_opml(conn, document),
+            None => Ok("Send the OPML file as a document with /importopml as the caption.".to_string()),
+        },
         AdminCommand::GetItem { feed_id, index } => get_item_from_feed(conn, feed_id, index), // in format YYY-mm-dd
         AdminCommand::AdminHelp => Ok(AdminCommand::descriptions().to_string()),
         AdminCommand::Users => get_users(conn), // in format YYY-mm-dd
         AdminCommand::AsUser { id, msg } => as_user(conn, id, &msg).await, // in format YYY-mm-dd
         AdminCommand::CleanUsers => clean_users(conn),
+        AdminCommand::FeedHealth => feed_health(conn),
+        AdminCommand::SetInterval { feed_id, minutes } => set_interval(conn, feed_id, minutes),
         AdminCommand::Update => db::sqlite::update_channels(conn)
             .await
             .map(|_| "Updated channels".to_string())
             .map_err(|e| e.into()),
+    };
+    answer.map(Reply::Text)
+}
+
+/// Adds the feeds of the OPML file that do not exist yet. They are public
+/// and downloaded at the next update once they have subscribers.
+pub fn import_opml(conn: &Connection, opml: &str) -> CustomResult<String> {
+    let opml_feeds = match parse_opml(opml) {
+        Ok(opml_feeds) => opml_feeds,
+        Err(e) => return Ok(format!("Could not read the OPML file: {}", e)),
+    };
+    let mut feeds = db::sqlite::get_feeds(conn)?;
+    let (mut added, mut existing, mut invalid) = (Vec::new(), 0, Vec::new());
+    for opml_feed in opml_feeds {
+        let feed = match PubmedFeed::build_from_link(&opml_feed.link, &opml_feed.name) {
+            Ok(feed) => feed,
+            Err(e) => {
+                invalid.push(format!("{} ({})", opml_feed.link, e));
+                continue;
+            }
+        };
+        if feeds.iter().any(|other| {
+            other.link == feed.link || (feed.uid.is_some() && other.uid == feed.uid)
+        }) {
+            existing += 1;
+            continue;
+        }
+        let uid = db::sqlite::add_feed(conn, &feed)?;
+        added.push(format!("{} - {}", uid, feed.name));
+        feeds.push(PubmedFeed { uid: Some(uid), ..feed });
+    }
+    let mut r = format!("Added {} feeds, {} already existed.\n", added.len(), existing);
+    for feed in added.iter() {
+        r.push_str(&format!("{}\n", feed));
+    }
+    if !invalid.is_empty() {
+        r.push_str(&format!("\nSkipped {} invalid links:\n{}", invalid.len(), invalid.join("\n")));
+    }
+    Ok(r.trim_end().to_string())
+}
+
+fn set_feed_visibility(conn: &Connection, feed_id: u32, visibility: String) -> CustomResult<String> {
+    let Ok(visibility) = Visibility::from_str(&visibility) else {
+        return Ok(format!("'{}' is not a valid visibility! Pick private, shared or public.", visibility));
+    };
+    let Some(mut feed) = db::sqlite::get_feed(conn, feed_id)? else {
+        return Ok(format!("Feed {} does not exist.", feed_id));
+    };
+    feed.visibility = visibility;
+    db::sqlite::update_feed(conn, &feed)?;
+    Ok(format!("Feed {} ({}) is now {}.", feed_id, feed.name, visibility))
+}
+
+fn rename_feed(conn: &Connection, feed_id: u32, name: String) -> CustomResult<String> {
+    let Some(mut feed) = db::sqlite::get_feed(conn, feed_id)? else {
+        return Ok(format!("Feed {} does not exist.", feed_id));
+    };
+    let old_name = std::mem::replace(&mut feed.name, name);
+    db::sqlite::update_feed(conn, &feed)?;
+    Ok(format!("Renamed feed {} from {} to {}.", feed_id, old_name, feed.name))
+}
+
+fn set_feed_link(conn: &Connection, feed_id: u32, link: String) -> CustomResult<String> {
+    let Some(mut feed) = db::sqlite::get_feed(conn, feed_id)? else {
+        return Ok(format!("Feed {} does not exist.", feed_id));
+    };
+    if feed.query.is_some() {
+        return Ok(format!("Feed {} is a PubMed search: its link follows the search term.", feed_id));
+    }
+    // Checks the link
+    let checked = PubmedFeed::build_from_link(&link, &feed.name)?;
+    if let Some(other) = db::sqlite::get_feeds(conn)?
+        .into_iter()
+        .find(|other| other.link == checked.link && other.uid != feed.uid)
+    {
+        return Ok(format!(
+            "Feed {} already has this link: merge the feeds with /mergefeeds {} {}.",
+            other.uid.unwrap_or_default(),
+            feed_id,
+            other.uid.unwrap_or_default()
+        ));
+    }
+    feed.link = checked.link;
+    // The next download is a new one
+    feed.etag = None;
+    feed.last_modified = None;
+    feed.health = Default::default();
+    db::sqlite::update_feed(conn, &feed)?;
+    Ok(format!("Feed {} ({}) now uses {}.", feed_id, feed.name, feed.link))
+}
+
+/// Removes the feed from every collection, deletes it and tells its subscribers.
+/// Feeds of the journal presets can not be deleted, /addpresettocollection would add them again.
+fn delete_feed(conn: &Connection, feed_id: u32) -> CustomResult<Reply> {
+    let Some(feed) = db::sqlite::get_feed(conn, feed_id)? else {
+        return Ok(format!("Feed {} does not exist.", feed_id).into());
+    };
+    if let Some(preset) = preset::journal_preset_with(feed_id) {
+        return Ok(format!("Feed {} is in the {:?} preset and can not be deleted.", feed_id, preset).into());
+    }
+    let mut users = Vec::new();
+    let mut messages = Vec::new();
+    for mut user in db::sqlite::get_users(conn)? {
+        let mut collections = Vec::new();
+        for (index, collection) in user.rss_lists.iter_mut().enumerate() {
+            if collection.feeds.remove(&feed_id) {
+                collections.push(index.to_string());
+            }
+        }
+        if !collections.is_empty() {
+            messages.push((
+                user.chat_id,
+                format!(
+                    "The feed {} ({}) was deleted, and removed from your collection(s) {}.",
+                    feed_id,
+                    feed.name,
+                    collections.join(", ")
+                ),
+            ));
+            users.push(user);
+        }
+    }
+    db::sqlite::delete_feed(conn, feed_id, &users)?;
+    Ok(Reply::Notify {
+        text: format!(
+            "Deleted feed {} ({}) and notified {} subscribers.",
+            feed_id,
+            feed.name,
+            messages.len()
+        ),
+        messages,
+    })
+}
+
+/// Replaces the feed by `into_feed_id` in every collection and deletes it. The
+/// seen items and archived articles move to the other feed.
+fn merge_feeds(conn: &Connection, feed_id: u32, into_feed_id: u32) -> CustomResult<String> {
+    if feed_id == into_feed_id {
+        return Ok("Can not merge a feed into itself.".to_string());
+    }
+    let (Some(feed), Some(into)) = (
+        db::sqlite::get_feed(conn, feed_id)?,
+        db::sqlite::get_feed(conn, into_feed_id)?,
+    ) else {
+        return Ok(format!("Feed {} or {} does not exist.", feed_id, into_feed_id));
+    };
+    if let Some(preset) = preset::journal_preset_with(feed_id) {
+        return Ok(format!(
+            "Feed {} is in the {:?} preset and can not be merged into another feed.",
+            feed_id, preset
+        ));
+    }
+    let mut users = Vec::new();
+    for mut user in db::sqlite::get_users(conn)? {
+        let mut changed = false;
+        for collection in user.rss_lists.iter_mut() {
+            if collection.feeds.remove(&feed_id) {
+                collection.feeds.insert(into_feed_id);
+                changed = true;
+            }
+        }
+        if changed {
+            users.push(user);
+        }
     }
+    db::sqlite::merge_feed(conn, feed_id, into_feed_id, &users)?;
+    Ok(format!(
+        "Merged feed {} ({}) into {} ({}), for {} users.",
+        feed_id, feed.name, into_feed_id, into.name, users.len()
+    ))
+}
+
+fn feed_health(conn: &Connection) -> CustomResult<String> {
+    let feeds = db::sqlite::get_feeds(conn)?;
+    Ok(health_report(&feeds, Local::now()))
+}
+
+fn set_interval(conn: &Connection, feed_id: u32, minutes: u32) -> CustomResult<String> {
+    let Some(mut feed) = db::sqlite::get_feed(conn, feed_id)? else {
+        return Ok(format!("Feed {} does not exist.", feed_id));
+    };
+    feed.refresh_interval = (minutes > 0).then_some(minutes);
+    db::sqlite::update_feed(conn, &feed)?;
+    Ok(format!(
+        "Feed {} ({}) is now downloaded every {} minutes{}.",
+        feed_id,
+        feed.name,
+        feed.refresh_interval().num_minutes(),
+        if minutes == 0 { ", learned from its items" } else { "" }
+    ))
 }
 
 fn clean_users(conn: &Connection) -> CustomResult<String>{
@@ -506,6 +1175,22 @@ fn clean_users(conn: &Connection) -> CustomResult<String>{
     Ok(format!("Removed {} users from the database.", cleaned))
 }
 
+fn rename_parser(s: String) -> Result<(u32, String), ParseError> {
+    match s.trim_start().split_once(" ") {
+        Some((feed_id, name)) if !name.trim().is_empty() => {
+            let feed_id = feed_id
+                .parse::<u32>()
+                .map_err(|e| ParseError::IncorrectFormat(e.into()))?;
+            Ok((feed_id, name.trim().to_string()))
+        }
+        _ => Err(ParseError::Custom(
+            "Wrong command. Provide a feed id and a name, divided with spaces."
+                .to_string()
+                .into(),
+        )),
+    }
+}
+
 fn as_user_parser(s: String) -> Result<(i64, String), ParseError> {
     match s.find(" ") {
         Some(first_space) => {
@@ -522,12 +1207,272 @@ fn as_user_parser(s: String) -> Result<(i64, String), ParseError> {
     }
 }
 
+fn template_parser(s: String) -> Result<(usize, String), ParseError> {
+    match s.trim_start().split_once(" ") {
+        Some((index, template)) => {
+            let index = index
+                .parse::<usize>()
+                .map_err(|e| ParseError::IncorrectFormat(e.into()))?;
+            Ok((index, template.trim().to_string()))
+        }
+        None => Err(ParseError::Custom(
+            "Wrong command. Provide a collection number and a template, divided with spaces."
+                .to_string()
+                .into(),
+        )),
+    }
+}
+
+fn search_parser(s: String) -> Result<(String, String), ParseError> {
+    match s.trim_start().split_once(" ") {
+        Some((name, query)) if !query.trim().is_empty() => {
+            Ok((name.to_string(), query.trim().to_string()))
+        }
+        _ => Err(ParseError::Custom(
+            "Wrong command. Provide a name and a search term, divided with spaces."
+                .to_string()
+                .into(),
+        )),
+    }
+}
+
+fn journal_name_parser(s: String) -> Result<(String,), ParseError> {
+    let name = s.trim();
+    if name.is_empty() {
+        return Err(ParseError::Custom(
+            "Wrong command. Provide the name of the journal.".to_string().into(),
+        ));
+    }
+    Ok((name.to_string(),))
+}
+
+/// The last word is the page number if it is a number, the first page otherwise.
+fn search_page_parser(s: String) -> Result<(String, usize), ParseError> {
+    let s = s.trim();
+    if let Some((query, page)) = s.rsplit_once(' ')
+        && let Ok(page) = page.parse::<usize>()
+    {
+        return Ok((query.trim().to_string(), page.max(1)));
+    }
+    if s.is_empty() {
+        return Err(ParseError::Custom(
+            "Wrong command. Provide the words to search for.".to_string().into(),
+        ));
+    }
+    Ok((s.to_string(), 1))
+}
+
+fn since_parser(s: String) -> Result<(String, Option<usize>), ParseError> {
+    let mut parts = s.split_whitespace();
+    let Some(date) = parts.next() else {
+        return Err(ParseError::Custom(
+            "Wrong command. Provide a date (YYYY-MM-DD) and optionally a collection number.".to_string().into(),
+        ));
+    };
+    let collection_index = parts
+        .next()
+        .map(|index| index.parse::<usize>().map_err(|e| ParseError::IncorrectFormat(e.into())))
+        .transpose()?;
+    if parts.next().is_some() {
+        return Err(ParseError::TooManyArguments {
+            expected: 2,
+            found: s.split_whitespace().count(),
+            message: "Provide a date and optionally a collection number.".to_string(),
+        });
+    }
+    Ok((date.to_string(), collection_index))
+}
+
+fn links_parser(s: String) -> Result<(Vec<String>,), ParseError> {
+    Ok((s.split_whitespace().map(|link| link.to_string()).collect(),))
+}
+
+fn list_links(user: &User) -> CustomResult<String> {
+    let templates = link_templates();
+    let mut r = "Available links:\n".to_string();
+    for link in templates {
+        r.push_str(&format!("{}{}\n", link.name.replace(' ', "_"), if link.default { " (default)" } else { "" }));
+    }
+    let chosen: Vec<&str> = select_links(templates, user.links.as_deref())
+        .iter()
+        .map(|link| link.name.as_str())
+        .collect();
+    r.push_str(&format!("\nYour articles show: {}", chosen.join(", ")));
+    Ok(r)
+}
+
+fn set_links(conn: &Connection, user: &mut User, links: Vec<String>) -> CustomResult<String> {
+    if links.is_empty() || (links.len() == 1 && links[0].eq_ignore_ascii_case("default")) {
+        user.links = None;
+        db::sqlite::update_user(conn, user)?;
+        return Ok("Your articles show the default links again.".to_string());
+    }
+    let mut chosen = Vec::new();
+    for name in links.iter() {
+        match find_link(link_templates(), name) {
+            Some(link) => chosen.push(link.name.clone()),
+            None => {
+                return Ok(format!(
+                    "'{}' is not an available link! See /links for the list.",
+                    name
+                ));
+            }
+        }
+    }
+    let response = format!("Your articles now show: {}", chosen.join(", "));
+    user.links = Some(chosen);
+    db::sqlite::update_user(conn, user)?;
+    Ok(response)
+}
+
+fn export_messages(messages: &[PreppedMessage], format: ExportFormat, name: &str) -> Reply {
+    Reply::Document {
+        file_name: format!("{}.{}", name, format.file_extension()),
+        content: export(messages, format),
+        caption: format!("Exported {} articles.", messages.len()),
+    }
+}
+
+fn export_collection(
+    conn: &Connection,
+    user: &User,
+    format: String,
+    collection_index: usize,
+) -> CustomResult<Reply> {
+    let Ok(format) = ExportFormat::from_str(&format) else {
+        return Ok(format!("'{}' is not a valid format! Pick ris, bibtex or csljson.", format).into());
+    };
+    let Some(collection) = user.rss_lists.get(collection_index) else {
+        return Ok(format!(
+            "The index is out of range: pick a number between 0 and {}",
+            user.rss_lists.len().saturating_sub(1)
+        )
+        .into());
+    };
+    let mut feeds = Vec::new();
+    for feed_id in collection.feeds.iter() {
+        if let Some(feed) = db::sqlite::get_feed(conn, *feed_id)? {
+            feeds.push((ItemMetadata { feed_id: Some(*feed_id), ..Default::default() }, feed));
+        }
+    }
+    let messages: Vec<PreppedMessage> = feeds
+        .iter()
+        .flat_map(|(item_metadata, feed)| {
+            feed.channel
+                .items()
+                .iter()
+                .filter(|item| collection.filter_item(item))
+                .map(move |item| PreppedMessage::build(item, item_metadata))
+        })
+        .collect();
+    if messages.is_empty() {
+        return Ok(format!("There are no recent articles in collection {}.", collection_index).into());
+    }
+    Ok(export_messages(&messages, format, &format!("collection{}", collection_index)))
+}
+
+/// A folder for every collection. The PubMed searches are left out, as they are no RSS feeds.
+fn export_opml(conn: &Connection, user: &User) -> CustomResult<Reply> {
+    let (mut groups, mut count, mut searches) = (Vec::new(), 0, 0);
+    for (index, collection) in user.rss_lists.iter().enumerate() {
+        let mut feeds = Vec::new();
+        for feed_id in collection.feeds.iter() {
+            match db::sqlite::get_feed(conn, *feed_id)? {
+                Some(feed) if feed.query.is_some() => searches += 1,
+                Some(feed) => feeds.push(OpmlFeed { name: feed.name, link: feed.link }),
+                None => (),
+            }
+        }
+        count += feeds.len();
+        groups.push((format!("Collection {}", index), feeds));
+    }
+    if count == 0 {
+        return Ok("Your collections have no feeds to export.".to_string().into());
+    }
+    let mut caption = format!("Exported {} feeds.", count);
+    if searches > 0 {
+        caption.push_str(&format!(" {} PubMed searches are not included as they are no RSS feeds.", searches));
+    }
+    Ok(Reply::Document {
+        file_name: "rssnotify.opml".to_string(),
+        content: to_opml("rssnotify", &groups),
+        caption,
+    })
+}
+
+fn export_article(conn: &Connection, format: String, pmid: u32) -> CustomResult<Reply> {
+    let Ok(format) = ExportFormat::from_str(&format) else {
+        return Ok(format!("'{}' is not a valid format! Pick ris, bibtex or csljson.", format).into());
+    };
+    for feed in db::sqlite::get_feeds(conn)? {
+        if let Some(item) = feed
+            .channel
+            .items()
+            .iter()
+            .find(|item| ChannelWrapper::parse_guid(item) == Some(pmid))
+        {
+            let item_metadata = ItemMetadata { feed_id: feed.uid, ..Default::default() };
+            let message = PreppedMessage::build(item, &item_metadata);
+            return Ok(export_messages(&[message], format, &pmid.to_string()));
+        }
+    }
+    Ok(format!("No recent article found with PMID {}.", pmid).into())
+}
+
+fn search(conn: &Connection, user: &User, query: String, page: usize) -> CustomResult<String> {
+    // A page far past the last one only counts the matches
+    let (limit, offset) = match page
+        .checked_sub(1)
+        .and_then(|page| page.checked_mul(SEARCH_PAGE_SIZE))
+        .filter(|offset| i64::try_from(*offset).is_ok())
+    {
+        Some(offset) => (SEARCH_PAGE_SIZE, offset),
+        None => (0, 0),
+    };
+    let (articles, total) = db::sqlite::search_articles(conn, user.chat_id, &query, limit, offset)?;
+    if total == 0 {
+        return Ok(format!("No articles found for '{}'.", query));
+    }
+    if articles.is_empty() {
+        return Ok(format!(
+            "There are only {} pages of results for '{}'.",
+            total.div_ceil(SEARCH_PAGE_SIZE),
+            query
+        ));
+    }
+    let item_metadata = ItemMetadata {
+        style: MessageStyle::Standard,
+        links: user.links.clone(),
+        ..Default::default()
+    };
+    let mut r = format!(
+        "Articles {}-{} of {} for '{}':\n\n",
+        offset + 1,
+        offset + articles.len(),
+        total,
+        query
+    );
+    for article in articles.iter() {
+        r.push_str(&article.to_message(&item_metadata).render(MessageFormat::PlainText));
+        r.push_str("\n\n");
+    }
+    if offset + articles.len() < total {
+        r.push_str(&format!("More results: /search {} {}", query, page + 1));
+    }
+    Ok(r.trim_end().to_string())
+}
+
 fn get_item_from_feed(conn: &Connection, feed_id: u32, index: usize) -> CustomResult<String> {
     match db::sqlite::get_feed(conn, feed_id)? {
         Some(feed) => {
             match feed.channel.items.get(index) {
-                Some(item) => Ok(PreppedMessage::build(item, &ItemMetadata::default())
-                    .format(ParseMode::MarkdownV2)),
+                Some(item) => {
+                    let item_metadata = ItemMetadata {
+                        feed_id: Some(feed_id),
+                        ..Default::default()
+                    };
+                    Ok(PreppedMessage::build(item, &item_metadata).format(ParseMode::MarkdownV2))
+                }
                 None => Ok("Index out of bounds!".to_string()),
             }
         }
@@ -538,8 +1483,22 @@ fn get_item_from_feed(conn: &Connection, feed_id: u32, index: usize) -> CustomRe
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::archive::Article;
+    use crate::testserver::{self, Response};
     use chrono::prelude::*;
 
+    /// Points the new searches to E-utilities without results.
+    fn serve_eutils() {
+        static EUTILS: std::sync::Once = std::sync::Once::new();
+        EUTILS.call_once(|| {
+            let base_url = testserver::serve(usize::MAX, |request| {
+                assert!(request.contains("/esearch.fcgi"));
+                Response::ok(r#"{"esearchresult": {"count": "0", "idlist": []}}"#)
+            });
+            crate::eutils::set_eutils_base_url(base_url).unwrap();
+        });
+    }
+
     #[test]
     fn test_as_user_parser() {
         assert_eq!(
@@ -550,6 +1509,410 @@ mod tests {
         assert!(as_user_parser("1234".to_string()).is_err());
     }
 
+    #[test]
+    fn test_template_parser() {
+        assert_eq!(
+            template_parser("0 {title} ({journal}) {links}".to_string()).unwrap(),
+            (0, "{title} ({journal}) {links}".to_string())
+        );
+        assert!(template_parser("a {title}".to_string()).is_err());
+        assert!(template_parser("1".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_set_links() {
+        let conn = db::sqlite::new_in_mem().unwrap();
+        let mut user = User::new(1);
+        db::sqlite::add_user(&conn, &user).unwrap();
+
+        set_links(&conn, &mut user, vec!["qxmd".to_string(), "Link".to_string()]).unwrap();
+        assert_eq!(user.links, Some(vec!["QxMD".to_string(), "Link".to_string()]));
+        let stored = db::sqlite::get_user(&conn, 1).unwrap().unwrap();
+        assert_eq!(stored.links, user.links);
+
+        let response = set_links(&conn, &mut user, vec!["Unknown".to_string()]).unwrap();
+        assert!(response.contains("not an available link"));
+        assert!(user.links.is_some());
+
+        set_links(&conn, &mut user, vec!["default".to_string()]).unwrap();
+        assert_eq!(db::sqlite::get_user(&conn, 1).unwrap().unwrap().links, None);
+    }
+
+    #[tokio::test]
+    async fn test_newsearch() {
+        let command = Command::parse("/newsearch endometriosis endometriosis[mesh] AND mri", "bot").unwrap();
+        assert_eq!(
+            command,
+            Command::NewSearch {
+                name: "endometriosis".to_string(),
+                query: "endometriosis[mesh] AND mri".to_string()
+            }
+        );
+        assert!(Command::parse("/newsearch endometriosis", "bot").is_err());
+
+        serve_eutils();
+        let conn = db::sqlite::new_in_mem().unwrap();
+        newsearch(&conn, &User::new(1), "endometriosis".to_string(), "endometriosis[mesh] AND mri".to_string()).await.unwrap();
+        let feed = db::sqlite::get_feeds(&conn)
+            .unwrap()
+            .into_iter()
+            .find(|feed| feed.query.is_some())
+            .unwrap();
+        assert_eq!(feed.query.as_deref(), Some("endometriosis[mesh] AND mri"));
+        assert_eq!(feed.link, "https://pubmed.ncbi.nlm.nih.gov/?term=endometriosis%5Bmesh%5D+AND+mri");
+    }
+
+    #[test]
+    fn test_feed_health() {
+        let conn = db::sqlite::new_in_mem().unwrap();
+        assert!(feed_health(&conn).unwrap().starts_with("All "));
+
+        let mut feed = db::sqlite::get_feeds(&conn).unwrap().remove(0);
+        feed.health.record_failure(Local::now(), "404 Not Found");
+        feed.health.malformed_items = 3;
+        db::sqlite::update_feed(&conn, &feed).unwrap();
+        let stored = db::sqlite::get_feed(&conn, feed.uid.unwrap()).unwrap().unwrap();
+        assert_eq!(stored.health, feed.health);
+        let report = feed_health(&conn).unwrap();
+        assert!(report.starts_with(&format!("{} - {}", feed.uid.unwrap(), feed.name)));
+        assert!(report.contains("failed 1 times in a row: 404 Not Found"));
+        assert!(report.contains("skipped 3 malformed items in the last download"));
+    }
+
+    #[test]
+    fn test_set_interval() {
+        let conn = db::sqlite::new_in_mem().unwrap();
+        let feed_id = db::sqlite::get_feeds(&conn).unwrap()[0].uid.unwrap();
+        let response = set_interval(&conn, feed_id, 60 * 24 * 7).unwrap();
+        assert!(response.ends_with("every 10080 minutes."));
+        let feed = db::sqlite::get_feed(&conn, feed_id).unwrap().unwrap();
+        assert_eq!(feed.refresh_interval, Some(10080));
+
+        set_interval(&conn, feed_id, 0).unwrap();
+        let feed = db::sqlite::get_feed(&conn, feed_id).unwrap().unwrap();
+        assert_eq!(feed.refresh_interval, None);
+        assert!(set_interval(&conn, 1, 60).unwrap().contains("does not exist"));
+    }
+
+    #[test]
+    fn test_search() {
+        assert_eq!(
+            Command::parse("/search adrenal vein sampling 2", "bot").unwrap(),
+            Command::Search { query: "adrenal vein sampling".to_string(), page: 2 }
+        );
+        assert_eq!(
+            Command::parse("/search \"covid 19\"", "bot").unwrap(),
+            Command::Search { query: "\"covid 19\"".to_string(), page: 1 }
+        );
+        assert!(Command::parse("/search", "bot").is_err());
+
+        let conn = db::sqlite::new_in_mem().unwrap();
+        let feed_id = db::sqlite::get_feeds(&conn).unwrap()[0].uid.unwrap();
+        let user = User::new(1);
+        for pmid in 1..=7 {
+            let article = Article {
+                key: format!("pmid:{}", pmid),
+                pmid: Some(pmid),
+                title: format!("Adrenal vein sampling {}", pmid),
+                abstract_text: Some("An abstract.".to_string()),
+                ..Default::default()
+            };
+            db::sqlite::archive_article(&conn, feed_id, &article, 100).unwrap();
+        }
+        let first = search(&conn, &user, "adrenal".to_string(), 1).unwrap();
+        assert!(first.starts_with("Articles 1-5 of 7 for 'adrenal':"));
+        assert!(first.contains("An abstract."));
+        assert!(first.ends_with("More results: /search adrenal 2"));
+        let second = search(&conn, &user, "adrenal".to_string(), 2).unwrap();
+        assert!(second.starts_with("Articles 6-7 of 7"));
+        assert!(!second.contains("More results"));
+        assert!(search(&conn, &user, "adrenal".to_string(), 3).unwrap().starts_with("There are only 2 pages"));
+        assert!(search(&conn, &user, "adrenal".to_string(), usize::MAX).unwrap().starts_with("There are only 2 pages"));
+        assert!(search(&conn, &user, "adrenal".to_string(), 0).unwrap().starts_with("There are only 2 pages"));
+        assert!(search(&conn, &user, "liver".to_string(), 1).unwrap().starts_with("No articles found"));
+    }
+
+    #[test]
+    fn test_since() {
+        assert_eq!(
+            Command::parse("/since 2025-03-01 1", "bot").unwrap(),
+            Command::Since { date: "2025-03-01".to_string(), collection_index: Some(1) }
+        );
+        assert_eq!(
+            Command::parse("/since 2025-03-01", "bot").unwrap(),
+            Command::Since { date: "2025-03-01".to_string(), collection_index: None }
+        );
+        assert!(Command::parse("/since", "bot").is_err());
+
+        let conn = db::sqlite::new_in_mem().unwrap();
+        let march = Local.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap().timestamp();
+        let articles = [
+            (1, "MRI of the adrenal glands", march - 24 * 60 * 60),
+            (1, "Adrenal vein sampling", march),
+            (2, "Adrenal vein sampling", march),
+            (2, "CT of the adrenal glands", march),
+            (3, "Adrenal incidentalomas", march),
+        ];
+        for (feed_id, title, first_seen) in articles {
+            let article = Article {
+                key: title.to_string(),
+                title: title.to_string(),
+                ..Default::default()
+            };
+            db::sqlite::archive_article(&conn, feed_id, &article, first_seen).unwrap();
+        }
+        let mut user = User::new(1);
+        let mut collection = UserRssList::new();
+        collection.feeds = HashSet::from([1, 2]);
+        collection.whitelist = HashSet::from(["adrenal".to_string()]);
+        collection.blacklist = HashSet::from(["ct of".to_string()]);
+        user.rss_lists.push(collection);
+
+        let Reply::Items { text, items } = since(&conn, &user, "2025-03-01".to_string(), None).unwrap() else {
+            panic!("No items");
+        };
+        // Once, though it is in both feeds
+        assert_eq!(text, "Sending 1 articles since 2025-03-01.");
+        assert_eq!(items[0].0.title(), Some("Adrenal vein sampling"));
+        assert_eq!(items[0].1.feed_id, Some(1));
+        assert_eq!(items[0].1.highlights, vec!["adrenal".to_string()]);
+
+        let reply = since(&conn, &user, "2025-04-01".to_string(), Some(0)).unwrap();
+        assert_eq!(reply, Reply::Text("No matching articles since 2025-04-01.".to_string()));
+        assert!(since(&conn, &user, "2025-03-01".to_string(), Some(1)).unwrap().to_string().starts_with("The index is out of range"));
+        assert!(since(&conn, &user, "march".to_string(), None).unwrap().to_string().starts_with("'march' is not a valid date"));
+    }
+
+    #[test]
+    fn test_backlog() {
+        let conn = db::sqlite::new_in_mem().unwrap();
+        let mut feed = db::sqlite::get_feeds(&conn).unwrap().remove(0);
+        let feed_id = feed.uid.unwrap();
+        let items: Vec<Item> = (1..=5)
+            .map(|day| {
+                rss::ItemBuilder::default()
+                    .title(Some(format!("Adrenal article {}", day)))
+                    .guid(Some(rss::GuidBuilder::default().value(format!("pubmed:{}", day)).build()))
+                    .pub_date(Some(Local.with_ymd_and_hms(2025, 3, day, 6, 0, 0).unwrap().to_rfc2822()))
+                    .build()
+            })
+            .rev()
+            .collect();
+        feed.channel = ChannelWrapper::build(rss::ChannelBuilder::default().items(items).build());
+        db::sqlite::update_feed(&conn, &feed).unwrap();
+
+        let mut user = User::new(1);
+        let mut collection = UserRssList::new();
+        collection.whitelist = HashSet::from(["adrenal".to_string()]);
+        user.rss_lists.push(collection);
+        db::sqlite::add_user(&conn, &user).unwrap();
+        assert!(set_backlog(&conn, &mut user, 2).unwrap().starts_with("The latest 2"));
+
+        let Reply::Items { text, items } = add_feed_to_collection(&conn, &mut user, feed_id, 0).unwrap() else {
+            panic!("No backlog");
+        };
+        assert!(text.ends_with("The latest 2 matching articles:"));
+        // The newest, sent in the order they were published
+        let titles: Vec<&str> = items.iter().map(|(item, _)| item.title().unwrap()).collect();
+        assert_eq!(titles, vec!["Adrenal article 4", "Adrenal article 5"]);
+        assert_eq!(db::sqlite::get_user(&conn, 1).unwrap().unwrap().backlog, 2);
+        // Not again when the feed is already in the collection
+        assert!(matches!(add_feed_to_collection(&conn, &mut user, feed_id, 0).unwrap(), Reply::Text(_)));
+
+        set_backlog(&conn, &mut user, 0).unwrap();
+        let mut other = UserRssList::new();
+        other.whitelist = HashSet::from(["adrenal".to_string()]);
+        user.rss_lists.push(other);
+        assert!(matches!(add_feed_to_collection(&conn, &mut user, feed_id, 1).unwrap(), Reply::Text(_)));
+    }
+
+    #[test]
+    fn test_feed_management() {
+        let conn = db::sqlite::new_in_mem().unwrap();
+        let preset_feed = db::sqlite::get_feeds(&conn).unwrap()[0].uid.unwrap();
+        let feeds: Vec<PubmedFeed> = ["first", "second"]
+            .iter()
+            .map(|name| PubmedFeed::build_from_link(&format!("https://journal.example.org/{name}.xml"), name).unwrap())
+            .collect();
+        let first = db::sqlite::add_feed(&conn, &feeds[0]).unwrap();
+        let second = db::sqlite::add_feed(&conn, &feeds[1]).unwrap();
+        let mut user = User::new(1);
+        let mut collection = UserRssList::new();
+        collection.feeds = HashSet::from([first]);
+        user.rss_lists = vec![collection.clone(), collection];
+        user.rss_lists[1].feeds.insert(second);
+        db::sqlite::add_user(&conn, &user).unwrap();
+        db::sqlite::update_subscribers(&conn).unwrap();
+        db::sqlite::mark_seen(&conn, first, &[10, 20], 100).unwrap();
+
+        rename_feed(&conn, first, "Renamed journal".to_string()).unwrap();
+        assert_eq!(db::sqlite::get_feed(&conn, first).unwrap().unwrap().name, "Renamed journal");
+        assert!(matches!(
+            AdminCommand::parse("/renamefeed 5 A new name", "bot").unwrap(),
+            AdminCommand::RenameFeed { feed_id: 5, name } if name == "A new name"
+        ));
+        assert!(set_feed_link(&conn, first, feeds[1].link.clone()).unwrap().contains("/mergefeeds"));
+        set_feed_link(&conn, first, "https://journal.example.org/rss".to_string()).unwrap();
+        assert_eq!(db::sqlite::get_feed(&conn, first).unwrap().unwrap().link, "https://journal.example.org/rss");
+
+        // /addpresettocollection would bring them back
+        assert!(delete_feed(&conn, preset_feed).unwrap().to_string().ends_with("preset and can not be deleted."));
+        assert!(merge_feeds(&conn, preset_feed, second).unwrap().ends_with("can not be merged into another feed."));
+        assert!(db::sqlite::get_feed(&conn, preset_feed).unwrap().is_some());
+
+        // Both collections get the second feed
+        assert!(merge_feeds(&conn, first, second).unwrap().ends_with("for 1 users."));
+        let user = db::sqlite::get_user(&conn, 1).unwrap().unwrap();
+        assert!(user.rss_lists.iter().all(|collection| collection.feeds == HashSet::from([second])));
+        assert!(db::sqlite::get_feed(&conn, first).unwrap().is_none());
+        assert_eq!(db::sqlite::get_seen_items(&conn, second).unwrap(), HashSet::from([10, 20]));
+        assert_eq!(db::sqlite::get_feed(&conn, second).unwrap().unwrap().subscribers, 2);
+
+        let Reply::Notify { text, messages } = delete_feed(&conn, second).unwrap() else {
+            panic!("No notifications");
+        };
+        assert!(text.ends_with("notified 1 subscribers."));
+        assert_eq!(messages[0].0, 1);
+        assert!(messages[0].1.ends_with("removed from your collection(s) 0, 1."));
+        let user = db::sqlite::get_user(&conn, 1).unwrap().unwrap();
+        assert!(user.rss_lists.iter().all(|collection| collection.feeds.is_empty()));
+        assert!(db::sqlite::get_seen_items(&conn, second).unwrap().is_empty());
+        assert_eq!(delete_feed(&conn, second).unwrap().to_string(), format!("Feed {} does not exist.", second));
+    }
+
+    #[tokio::test]
+    async fn test_feed_visibility() {
+        serve_eutils();
+        let conn = db::sqlite::new_in_mem().unwrap();
+        let (owner, mut other) = (User::new(1), User::new(2));
+        other.rss_lists.push(UserRssList::new());
+        db::sqlite::add_user(&conn, &other).unwrap();
+        let reply = newsearch(&conn, &owner, "adrenal".to_string(), "adrenal vein sampling".to_string()).await.unwrap();
+        let feed = db::sqlite::get_feeds(&conn).unwrap().into_iter().find(|feed| feed.query.is_some()).unwrap();
+        let feed_id = feed.uid.unwrap();
+        assert!(reply.ends_with(&format!("share it with others with /setvisibility {} shared.", feed_id)));
+        assert_eq!((feed.owner, feed.visibility), (Some(1), Visibility::Private));
+        assert!(list_feeds(&conn, &owner).unwrap().contains("adrenal"));
+        assert!(!list_feeds(&conn, &other).unwrap().contains("adrenal"));
+        assert_eq!(add_feed_to_collection(&conn, &mut other, feed_id, 0).unwrap().to_string(), format!("Feed {} does not exist.", feed_id));
+        // The same search again does not tell what the other user searches
+        let again = newsearch(&conn, &other, "mine".to_string(), "adrenal vein sampling".to_string()).await.unwrap();
+        assert_eq!(again, "This search can not be added. Ask an admin for help.");
+
+        // A dead link is not added
+        let base_url = testserver::serve(1, |_| Response::status(404));
+        let reply = newfeed(&conn, &owner, "dead".to_string(), format!("{}/rss.xml", base_url)).await.unwrap();
+        assert!(reply.starts_with("Could not download the feed dead"));
+        assert!(db::sqlite::get_feeds(&conn).unwrap().iter().all(|feed| feed.name != "dead"));
+
+        assert!(set_own_visibility(&conn, &other, feed_id, "shared".to_string()).unwrap().starts_with("You did not add"));
+        assert!(set_own_visibility(&conn, &owner, feed_id, "public".to_string()).unwrap().starts_with("Only an admin"));
+        set_own_visibility(&conn, &owner, feed_id, "shared".to_string()).unwrap();
+        assert!(!list_feeds(&conn, &other).unwrap().contains("adrenal"));
+        assert!(add_feed_to_collection(&conn, &mut other, feed_id, 0).unwrap().to_string().starts_with("Added"));
+
+        assert!(set_feed_visibility(&conn, feed_id, "Public".to_string()).unwrap().ends_with("is now public."));
+        assert!(list_feeds(&conn, &other).unwrap().contains("adrenal"));
+    }
+
+    #[tokio::test]
+    async fn test_journals() {
+        let catalogue = crate::journals::JournalCatalogue::from_file(std::path::Path::new("test/J_Medline.txt")).unwrap();
+        let _ = crate::journals::set_journal_catalogue(catalogue);
+        assert_eq!(
+            Command::parse("/findjournal magnetic resonance ", "bot").unwrap(),
+            Command::FindJournal { name: "magnetic resonance".to_string() }
+        );
+        assert!(Command::parse("/findjournal", "bot").is_err());
+        let found = find_journal("eur radiol").unwrap();
+        assert!(found.contains("9114774 - European radiology (Eur Radiol)"));
+        assert!(find_journal("cardiology").unwrap().starts_with("No journals found"));
+
+        let conn = db::sqlite::new_in_mem().unwrap();
+        let mut user = User::new(1);
+        user.rss_lists.push(UserRssList::new());
+        db::sqlite::add_user(&conn, &user).unwrap();
+        let feeds = db::sqlite::get_feeds(&conn).unwrap().len();
+        // The feed of European Radiology already exists
+        let reply = add_journal(&conn, &mut user, "9114774".to_string(), 0).await.unwrap();
+        assert!(reply.to_string().starts_with("Added 9114774"));
+        assert_eq!(db::sqlite::get_feeds(&conn).unwrap().len(), feeds);
+
+        // A new journal feed is downloaded, and the latest articles are sent
+        let base_url = testserver::serve(2, |request| {
+            if request.starts_with("GET /dead") {
+                return Response::status(404);
+            }
+            Response::ok(
+                r#"<rss version="2.0"><channel><title>J Cardiovasc Magn Reson</title><link>https://pubmed.ncbi.nlm.nih.gov</link><description></description>
+                <item><title>Cardiac MRI</title><guid isPermaLink="false">pubmed:40000001</guid></item>
+                <item><title>Late gadolinium enhancement</title><guid isPermaLink="false">pubmed:40000002</guid></item>
+                </channel></rss>"#,
+            )
+        });
+        user.rss_lists[0].whitelist = HashSet::from(["mri".to_string()]);
+        let journal = journal_catalogue().get("9815616").unwrap();
+        let link = format!("{}/rss/journals/9815616/?limit=15&utm_campaign=journals", base_url);
+        let feed = PubmedFeed::build_from_link(&link, &journal.title).unwrap();
+        let Reply::Items { text, items } = add_journal_feed(&conn, &mut user, feed, 0).await.unwrap() else {
+            panic!("No backlog");
+        };
+        assert!(text.starts_with("Added") && text.contains("(Journal of cardiovascular magnetic resonance"));
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].0.title(), Some("Cardiac MRI"));
+        let feed = db::sqlite::get_feeds(&conn).unwrap().into_iter().find(|feed| feed.link == link).unwrap();
+        assert_eq!(feed.channel.items().len(), 2);
+        assert_eq!(db::sqlite::get_seen_items(&conn, feed.uid.unwrap()).unwrap().len(), 2);
+        assert_eq!(feed.visibility, Visibility::Public);
+        assert_eq!(feed.owner, None);
+        assert!(user.rss_lists[0].feeds.contains(&feed.uid.unwrap()));
+        // A dead link is not added
+        let dead = PubmedFeed::build_from_link(&format!("{}/dead", base_url), "Dead journal").unwrap();
+        let reply = add_journal_feed(&conn, &mut user, dead, 0).await.unwrap().to_string();
+        assert!(reply.starts_with("Could not download the feed of Dead journal"));
+        assert_eq!(db::sqlite::get_feeds(&conn).unwrap().len(), feeds + 1);
+        assert!(add_journal(&conn, &mut user, "1".to_string(), 0).await.unwrap().to_string().starts_with("There is no journal"));
+    }
+
+    #[tokio::test]
+    async fn test_opml() {
+        let conn = db::sqlite::new_in_mem().unwrap();
+        let feeds = db::sqlite::get_feeds(&conn).unwrap().len();
+        let reply = admin_command_handler("/importopml", None, &conn).await.unwrap();
+        assert!(reply.to_string().starts_with("Send the OPML file"));
+        let opml = std::fs::read_to_string("test/feeds.opml").unwrap();
+        let reply = admin_command_handler("/importopml", Some(&opml), &conn).await.unwrap().to_string();
+        // Insights into Imaging already exists
+        assert!(reply.starts_with("Added 2 feeds, 1 already existed.\n1302751 - Neuroradiology\n"));
+        assert_eq!(db::sqlite::get_feeds(&conn).unwrap().len(), feeds + 2);
+        let feed = db::sqlite::get_feed(&conn, 1302751).unwrap().unwrap();
+        assert_eq!((feed.owner, feed.visibility), (None, Visibility::Public));
+        // Importing again adds nothing
+        assert!(import_opml(&conn, &opml).unwrap().starts_with("Added 0 feeds, 3 already existed."));
+        assert!(import_opml(&conn, "not xml").unwrap().starts_with("Could not read the OPML file"));
+
+        let mut user = User::new(1);
+        assert_eq!(export_opml(&conn, &user).unwrap().to_string(), "Your collections have no feeds to export.");
+        user.rss_lists.push(UserRssList::new());
+        user.rss_lists.push(UserRssList::new());
+        user.rss_lists[0].feeds.insert(101532453);
+        user.rss_lists[1].feeds.insert(1302751);
+        serve_eutils();
+        newsearch(&conn, &user, "adrenal".to_string(), "adrenal vein sampling".to_string()).await.unwrap();
+        let search_id = db::sqlite::get_feeds(&conn).unwrap().into_iter().find(|feed| feed.query.is_some()).unwrap().uid.unwrap();
+        user.rss_lists[1].feeds.insert(search_id);
+        let Reply::Document { file_name, content, caption } = export_opml(&conn, &user).unwrap() else {
+            panic!("The export is not a file");
+        };
+        assert_eq!(file_name, "rssnotify.opml");
+        assert_eq!(caption, "Exported 2 feeds. 1 PubMed searches are not included as they are no RSS feeds.");
+        let exported = parse_opml(&content).unwrap();
+        assert_eq!(exported.len(), 2);
+        assert!(content.contains("<outline text=\"Collection 1\""));
+        // The exported file adds nothing either
+        assert!(import_opml(&conn, &content).unwrap().starts_with("Added 0 feeds, 2 already existed."));
+    }
+
     #[test]
     fn test_date() {
         let date = "2025-03-01";
@@ -1,8 +1,13 @@
+use crate::eutils::DEFAULT_EUTILS_BASE_URL;
+use crate::http::{HttpSettings, MAX_REQUESTS_PER_FEED};
+use crate::refresh::RefreshSettings;
+use crate::links::{LinkTemplate, default_link_templates, merge_link_templates};
 use chrono::NaiveTime;
 use simple_expand_tilde::*;
 use std::fs;
 use std::path::Path;
 use std::str::FromStr;
+use std::time::Duration;
 use std::{env, error::Error, path::PathBuf};
 use toml::Table;
 
@@ -17,8 +22,16 @@ pub struct Config {
     pub update_time: String,
     pub log_level: log::LevelFilter,
     pub log_path: PathBuf,
+    pub format_rules_path: PathBuf,
+    pub journal_catalogue_path: PathBuf,
+    /// An OPML file with feeds to add before starting.
+    pub import_opml: Option<PathBuf>,
     pub admin: Option<u64>,
     pub send_collection: bool,
+    pub links: Vec<LinkTemplate>,
+    pub eutils_base_url: String,
+    pub refresh: RefreshSettings,
+    pub http: HttpSettings,
 }
 impl Default for Config {
     fn default() -> Self {
@@ -28,12 +41,19 @@ impl Default for Config {
             config_path: expand_tilde("~/.config/rssnotify/config.toml").unwrap(),
             db_path: expand_tilde("~/.config/rssnotify/database.db3").unwrap(),
             log_path: expand_tilde("~/.config/rssnotify/rssnotify.log").unwrap(),
+            format_rules_path: expand_tilde("~/.config/rssnotify/format_rules.toml").unwrap(),
+            journal_catalogue_path: expand_tilde("~/.config/rssnotify/J_Medline.txt").unwrap(),
+            import_opml: None,
             bot_token: None,
             persistent: true,
             update_time: parse_update_time("9-17").unwrap(),
             log_level: log::LevelFilter::Info,
             admin: None,
             send_collection: true,
+            links: default_link_templates(),
+            eutils_base_url: DEFAULT_EUTILS_BASE_URL.to_string(),
+            refresh: RefreshSettings::default(),
+            http: HttpSettings::default(),
         }
     }
 }
@@ -60,6 +80,8 @@ impl Config {
         self.log_path = expand_tilde("target/debug/rssnotify.log").unwrap();
         self.config_path = expand_tilde("rssnotify.toml").unwrap();
         self.db_path = expand_tilde("target/debug/database.db3").unwrap();
+        self.format_rules_path = expand_tilde("format_rules.toml").unwrap();
+        self.journal_catalogue_path = expand_tilde("J_Medline.txt").unwrap();
     }
 
     pub fn build_from_toml_and_args(args: &[String]) -> Result<Config, Box<dyn Error>> {
@@ -74,6 +96,10 @@ impl Config {
     pub fn apply_toml(&mut self, file_path: &Path) -> Result<(), Box<dyn Error>> {
         let content = fs::read_to_string(file_path)?;
         let table = content.parse::<Table>()?;
+        if let Some(links) = table.get("link") {
+            let links: Vec<LinkTemplate> = links.clone().try_into()?;
+            merge_link_templates(&mut self.links, links);
+        }
         let table = table["config"]
             .as_table()
             .ok_or("File does not contain a [config] header!")?;
@@ -111,6 +137,97 @@ impl Config {
                         return Err("Invalid value provided to log_path in the config file!".into());
                     }
                 },
+                "format_rules_path" => match table["format_rules_path"].as_str() {
+                    Some(s) => {
+                        self.format_rules_path =
+                            expand_tilde(s).ok_or("There was a problem parsing the path.")?
+                    }
+                    None => {
+                        return Err(
+                            "Invalid value provided to format_rules_path in the config file!"
+                                .into(),
+                        );
+                    }
+                },
+                "journal_catalogue_path" => match table["journal_catalogue_path"].as_str() {
+                    Some(s) => {
+                        self.journal_catalogue_path =
+                            expand_tilde(s).ok_or("There was a problem parsing the path.")?
+                    }
+                    None => {
+                        return Err(
+                            "Invalid value provided to journal_catalogue_path in the config file!"
+                                .into(),
+                        );
+                    }
+                },
+                "eutils_base_url" => match table["eutils_base_url"].as_str() {
+                    Some(s) => self.eutils_base_url = s.to_string(),
+                    None => {
+                        return Err(
+                            "Invalid value provided to eutils_base_url in the config file!".into(),
+                        );
+                    }
+                },
+                "max_concurrent_downloads" => match table["max_concurrent_downloads"].as_integer() {
+                    Some(n) if n > 0 => self.refresh.max_concurrent = n as usize,
+                    _ => {
+                        return Err(
+                            "Invalid value provided to max_concurrent_downloads in the config file!".into(),
+                        );
+                    }
+                },
+                "download_timeout" => match table["download_timeout"].as_integer() {
+                    Some(n) if n > 0 => self.refresh.timeout = Duration::from_secs(n as u64),
+                    _ => {
+                        return Err(
+                            "Invalid value provided to download_timeout in the config file!".into(),
+                        );
+                    }
+                },
+                "proxy" => match table["proxy"].as_str() {
+                    Some(s) => self.http.proxy = Some(s.to_string()),
+                    None => {
+                        return Err("Invalid value provided to proxy in the config file!".into());
+                    }
+                },
+                "http_timeout" => match table["http_timeout"].as_integer() {
+                    Some(n) if n > 0 => self.http.timeout = Duration::from_secs(n as u64),
+                    _ => {
+                        return Err(
+                            "Invalid value provided to http_timeout in the config file!".into(),
+                        );
+                    }
+                },
+                "http_retries" => match table["http_retries"].as_integer() {
+                    Some(n) if n >= 0 => self.http.retries = n as u32,
+                    _ => {
+                        return Err(
+                            "Invalid value provided to http_retries in the config file!".into(),
+                        );
+                    }
+                },
+                "user_agent" => match table["user_agent"].as_str() {
+                    Some(s) => self.http.user_agent = s.to_string(),
+                    None => {
+                        return Err("Invalid value provided to user_agent in the config file!".into());
+                    }
+                },
+                "email" => match table["email"].as_str() {
+                    Some(s) => self.http.email = Some(s.to_string()),
+                    None => {
+                        return Err("Invalid value provided to email in the config file!".into());
+                    }
+                },
+                "ca_bundle" => match table["ca_bundle"].as_str() {
+                    Some(s) => {
+                        self.http.ca_bundle =
+                            Some(expand_tilde(s).ok_or("There was a problem parsing the path.")?)
+                    }
+                    None => {
+                        return Err("Invalid value provided to ca_bundle in the config file!".into());
+                    }
+                },
                 "update_time" => {
                     if let Some(update_time) = table["update_time"].as_str() {
                         self.update_time = parse_update_time(update_time).unwrap()
@@ -124,6 +241,20 @@ impl Config {
                 _ => (),
             }
         }
+        self.check_timeouts()
+    }
+
+    /// A feed should not be given up while its requests are still being retried.
+    fn check_timeouts(&self) -> Result<(), Box<dyn Error>> {
+        let needed = self.http.max_request_time().saturating_mul(MAX_REQUESTS_PER_FEED);
+        if needed > self.refresh.timeout {
+            return Err(format!(
+                "download_timeout ({} s) is too short for http_timeout and http_retries: a feed can take {} s. Raise download_timeout or lower http_timeout or http_retries in the config file!",
+                self.refresh.timeout.as_secs(),
+                needed.as_secs()
+            )
+            .into());
+        }
         Ok(())
     }
 
@@ -153,6 +284,13 @@ impl Config {
                     }
                     None => return Err("No db path provided after -p / --db-path!".into()),
                 },
+                "--import-opml" => match it.next() {
+                    Some(f) => {
+                        self.import_opml =
+                            Some(expand_tilde(f).ok_or("There was a problem parsing the path.")?)
+                    }
+                    None => return Err("No OPML file provided after --import-opml!".into()),
+                },
                 "-t" | "--token" => match it.next() {
                     Some(f) => self.bot_token = Some(f.clone()),
                     None => return Err("No bot token name provided after -t / --token!".into()),
@@ -179,6 +317,15 @@ impl Config {
         log::info!("Filepath: {:#?}", self.config_path);
         log::info!("Database path: {:#?}", self.db_path);
         log::info!("Log path: {:#?}", self.log_path);
+        log::info!("Format rules path: {:#?}", self.format_rules_path);
+        log::info!("Journal catalogue path: {:#?}", self.journal_catalogue_path);
+        log::info!(
+            "Links: {:?}",
+            self.links.iter().map(|l| &l.name).collect::<Vec<&String>>()
+        );
+        log::info!("E-utilities url: {}", self.eutils_base_url);
+        log::info!("HTTP settings: {:?}", self.http);
+        log::info!("Refresh: {:?}", self.refresh);
         log::info!("Log level: {:?}", self.log_level);
     }
 }
@@ -259,5 +406,27 @@ mod tests {
         assert_eq!(config.bot_token.unwrap(), "MYBOT_TOKEN");
         assert_eq!(config.admin.unwrap(), 12345);
         assert_eq!(config.update_time, parse_update_time("9-17").unwrap());
+        assert_eq!(config.links.len(), 4);
+        assert_eq!(config.links[3].name, "Library");
+        assert!(!config.links[3].default);
+        assert_eq!(config.eutils_base_url, "http://localhost:8080/eutils");
+        assert_eq!(config.refresh.max_concurrent, 8);
+        assert_eq!(config.refresh.timeout, Duration::from_secs(60));
+        assert_eq!(config.http.proxy.as_deref(), Some("http://proxy.hospital.org:8080"));
+        assert_eq!(config.http.timeout, Duration::from_secs(8));
+        assert_eq!(config.http.retries, 2);
+        assert_eq!(config.http.email.as_deref(), Some("radiology@hospital.org"));
+        assert!(config.http.user_agent.starts_with("rssnotify/"));
+        assert_eq!(config.http.ca_bundle, None);
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let mut config = Config::default();
+        assert!(config.check_timeouts().is_ok());
+        // Two E-utilities requests of 3 x 15 s and 1 + 2 s of delays
+        config.http.timeout = Duration::from_secs(15);
+        config.http.retries = 2;
+        assert!(config.check_timeouts().unwrap_err().to_string().contains("a feed can take 96 s"));
     }
 }
@@ -1,12 +1,16 @@
 use crate::channelwrapper::ChannelWrapper;
-use crate::rsshandler::item_contains_keyword;
+use crate::eutils::{self, eutils_base_url};
+use crate::formatter::MessageStyle;
+use crate::health::FeedHealth;
+use crate::http::http_client;
+use crate::rsshandler::{item_contains_keyword, matched_keywords};
 use chrono::DateTime;
 use chrono::Local;
+use chrono::TimeDelta;
 use chrono::format::ParseResult;
 use core::str;
 use futures::future::join_all;
 use regex::Regex;
-use rss::Channel;
 use rss::Item;
 use serde::{Deserialize, Serialize};
 use serde_json;
@@ -16,6 +20,7 @@ use std::error::Error;
 use std::hash::{Hash, Hasher};
 use std::ops::Deref;
 use std::ops::DerefMut;
+use strum_macros::{Display, EnumString};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct User {
@@ -23,6 +28,19 @@ pub struct User {
     pub full_name: Option<String>,
     pub last_pushed: String, // of date
     pub rss_lists: Vec<UserRssList>,
+    /// The names of the links shown below an item, None for the default links.
+    #[serde(default)]
+    pub links: Option<Vec<String>>,
+    /// How many of the latest matching items are sent right away when feeds are
+    /// added to a collection, 0 for none.
+    #[serde(default = "default_backlog")]
+    pub backlog: u32,
+}
+
+pub const DEFAULT_BACKLOG: u32 = 3;
+
+fn default_backlog() -> u32 {
+    DEFAULT_BACKLOG
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -30,6 +48,8 @@ pub struct UserRssList {
     pub feeds: HashSet<u32>,
     pub whitelist: HashSet<String>,
     pub blacklist: HashSet<String>,
+    #[serde(default)]
+    pub style: MessageStyle,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -38,14 +58,86 @@ pub struct PubmedFeed {
     pub uid: Option<u32>,
     pub link: String,
     pub channel: ChannelWrapper,
-    pub last_pushed_guid: Option<u32>,
     pub subscribers: u32,
+    /// The PubMed search term of an E-utilities feed, None for RSS and Atom feeds.
+    #[serde(default)]
+    pub query: Option<String>,
+    /// The date (YYYY/MM/DD) of the last E-utilities search.
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// The ETag header of the last download, for conditional requests.
+    #[serde(default)]
+    pub etag: Option<String>,
+    /// The Last-Modified header of the last download, for conditional requests.
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    #[serde(default)]
+    pub health: FeedHealth,
+    /// Minutes between the downloads, set by the admin. None to learn it from the items.
+    #[serde(default)]
+    pub refresh_interval: Option<u32>,
+    /// The chat id of the user that added the feed, None for the feeds of the admin.
+    #[serde(default)]
+    pub owner: Option<i64>,
+    #[serde(default)]
+    pub visibility: Visibility,
+}
+
+/// Who can find a feed. The owner can always see and add their own feeds.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default, EnumString, Display)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum Visibility {
+    /// Only for the owner.
+    Private,
+    /// Not listed, but anyone with the id can add it.
+    Shared,
+    /// Listed in /feeds.
+    #[default]
+    Public,
+}
+
+/// The refresh interval of a feed when it can not be learned from the items.
+const DEFAULT_REFRESH_MINUTES: i64 = 60;
+const MAX_REFRESH_HOURS: i64 = 24;
+/// The updates run on the hour, and take some time.
+const REFRESH_SLACK_MINUTES: i64 = 5;
+
+/// The result of `PubmedFeed::update_channel_limited`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChannelUpdate {
+    /// Not downloaded: no subscribers, or updated less than an hour ago.
+    Skipped,
+    NotModified,
+    Modified,
+}
+
+/// The result of `PubmedFeed::download_channel`.
+#[derive(Debug)]
+pub enum Download {
+    Modified {
+        channel: Box<ChannelWrapper>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    /// The server answered 304 Not Modified.
+    NotModified,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
 pub struct ItemMetadata {
     pub collection: Option<usize>,
     pub keyword: Option<String>,
+    #[serde(default)]
+    pub style: MessageStyle,
+    /// The feed the item comes from, to find the formatting rules of the journal.
+    #[serde(default)]
+    pub feed_id: Option<u32>,
+    /// The links the user picked for the footer, None for the default links.
+    #[serde(default)]
+    pub links: Option<Vec<String>>,
+    /// The whitelist keywords the item matched, highlighted in the message.
+    #[serde(default)]
+    pub highlights: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -64,6 +156,8 @@ impl User {
             full_name,
             last_pushed,
             rss_lists,
+            links: None,
+            backlog: DEFAULT_BACKLOG,
         }
     }
     pub fn to_json(&self) -> serde_json::Result<String> {
@@ -137,49 +231,154 @@ impl Default for User {
             full_name: None,
             last_pushed: Local::now().to_rfc2822(),
             rss_lists: Vec::new(),
+            links: None,
+            backlog: DEFAULT_BACKLOG,
         }
     }
 }
 
 impl PubmedFeed {
     // let link = "https://pubmed.ncbi.nlm.nih.gov/rss/journals/101532453/?limit=5&name=Insights%20Imaging&utm_campaign=journals";
-    pub async fn download_channel(&self) -> Result<ChannelWrapper, Box<dyn Error + Sync + Send>> {
-        let content = reqwest::get(self.get_link()).await?.bytes().await?;
-        let channel = Channel::read_from(&content[..])?;
-        Ok(ChannelWrapper::build(channel))
+    /// Downloads the feed. Sends the ETag and Last-Modified of the previous
+    /// download, so an unchanged feed is not downloaded again.
+    pub async fn download_channel(&self) -> Result<Download, Box<dyn Error + Sync + Send>> {
+        if let Some(query) = &self.query {
+            let channel = eutils::download_channel(
+                eutils_base_url(),
+                query,
+                self.cursor.as_deref(),
+                &self.channel,
+            )
+            .await?;
+            return Ok(Download::Modified {
+                channel: Box::new(channel),
+                etag: None,
+                last_modified: None,
+            });
+        }
+        let client = http_client();
+        let mut request = client.get(self.get_link());
+        if let Some(etag) = &self.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = client.send(request).await?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(Download::NotModified);
+        }
+        let response = response.error_for_status()?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        let etag = header(reqwest::header::ETAG);
+        let last_modified = header(reqwest::header::LAST_MODIFIED);
+        let content = response.bytes().await?;
+        Ok(Download::Modified {
+            channel: Box::new(ChannelWrapper::read_from(&content[..])?),
+            etag,
+            last_modified,
+        })
     }
 
     // TODO is double function
     pub async fn update_channel(&mut self) -> Result<&PubmedFeed, Box<dyn Error + Sync + Send>> {
         log::info!("Updating feed {} ({:?})...", &self.name, &self.uid);
-        let newchannel = self.download_channel().await?;
-        self.channel = newchannel;
+        let download = self.download_channel().await?;
+        self.set_channel(download);
         log::info!("... Succesfully");
         Ok(self)
     }
 
-    pub async fn update_channel_limited(&mut self) -> Result<(), Box<dyn Error + Sync + Send>> {
+    /// Updates the channel, unless nobody is subscribed or it is not due yet.
+    pub async fn update_channel_limited(
+        &mut self,
+    ) -> Result<ChannelUpdate, Box<dyn Error + Sync + Send>> {
         // Don't update if no subscribers
         log::debug!("Updating feed {} ", self.name);
         if self.subscribers == 0 {
             log::debug!("Subscribers is 0, skipping update.");
-            return Ok(());
+            return Ok(ChannelUpdate::Skipped);
         }
-        // Don't update if update < 1 hour ago
-        if let Some(last_build_date) = self.channel.last_build_date() {
-            let prev: DateTime<Local> = DateTime::parse_from_rfc2822(last_build_date)
-                .unwrap_or_default()
-                .into();
-            let diff = Local::now() - prev;
-            if diff.num_minutes() < 55 {
-                log::debug!("Last update was < 1 hour: skipping update.");
-                return Ok(());
+        if !self.is_due(Local::now()) {
+            log::debug!(
+                "Last update was less than {} minutes ago: skipping update.",
+                self.refresh_interval().num_minutes()
+            );
+            return Ok(ChannelUpdate::Skipped);
+        }
+        let download = self.download_channel().await?;
+        if self.set_channel(download) {
+            log::debug!("Succesfully updated channel {}", &self.name);
+            Ok(ChannelUpdate::Modified)
+        } else {
+            log::debug!("Channel {} was not modified", &self.name);
+            Ok(ChannelUpdate::NotModified)
+        }
+    }
+
+    /// The interval set by the admin, or learned from the items.
+    pub fn refresh_interval(&self) -> TimeDelta {
+        match self.refresh_interval {
+            Some(minutes) => TimeDelta::minutes(minutes as i64),
+            None => self.learned_refresh_interval(),
+        }
+    }
+
+    /// A quarter of the average time between the items, so new items are found
+    /// within a day: between an hour for busy feeds and a day for weekly journals.
+    pub fn learned_refresh_interval(&self) -> TimeDelta {
+        match self.channel.average_item_gap() {
+            Some(gap) => (gap / 4).clamp(
+                TimeDelta::minutes(DEFAULT_REFRESH_MINUTES),
+                TimeDelta::hours(MAX_REFRESH_HOURS),
+            ),
+            None => TimeDelta::minutes(DEFAULT_REFRESH_MINUTES),
+        }
+    }
+
+    /// Whether the refresh interval passed since the last download.
+    pub fn is_due(&self, now: DateTime<Local>) -> bool {
+        // Feeds saved before the health was tracked only have the build date
+        let last_update = self.health.last_success_date().or_else(|| {
+            self.channel
+                .last_build_date()
+                .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+                .map(|date| date.into())
+        });
+        match last_update {
+            Some(last_update) => {
+                now - last_update
+                    >= self.refresh_interval() - TimeDelta::minutes(REFRESH_SLACK_MINUTES)
             }
+            None => true,
+        }
+    }
+
+    /// Sets the downloaded channel. E-utilities feeds only search the newer articles next time.
+    /// Returns false if the channel was not modified.
+    fn set_channel(&mut self, download: Download) -> bool {
+        match download {
+            Download::Modified {
+                channel,
+                etag,
+                last_modified,
+            } => {
+                self.channel = *channel;
+                self.etag = etag;
+                self.last_modified = last_modified;
+                if self.query.is_some() {
+                    self.cursor = Some(eutils::cursor_today());
+                }
+                true
+            }
+            Download::NotModified => false,
         }
-        let newchannel = self.download_channel().await?;
-        self.channel = newchannel;
-        log::debug!("Succesfully updated channel {}", &self.name);
-        Ok(())
     }
 
     pub fn set_uid(&mut self, newuid: u32) -> Option<u32> {
@@ -190,58 +389,103 @@ impl PubmedFeed {
         self.channel.get_new_items(fromdate)
     }
 
-    pub fn get_new_items_from_last(&self) -> Vec<&Item> {
-        if let Some(guid) = self.last_pushed_guid.as_ref() {
-            self.channel.get_new_items_from_last(guid)
-        } else {
-            // self.update_guid();
-            vec![]
+    /// The items that were not seen before. Nothing is new in a feed without
+    /// seen items: its current items are only marked as seen.
+    pub fn get_unseen_items(&self, seen: &HashSet<u32>) -> Vec<&Item> {
+        if seen.is_empty() {
+            return vec![];
         }
-    }
-
-    pub fn update_guid(&mut self) -> Option<u32> {
-        let firstitem = self.channel.items().iter().next();
-        if let Some(item) = firstitem {
-            log::debug!(
-                "Found guid of newest item {:?} in {}",
-                &item.guid,
-                &self.name
+        self.channel.get_unseen_items(seen)
+    }
+
+    /// Logs the items that are skipped because they can not be parsed, after
+    /// a download. Returns the number of skipped items, kept in the health.
+    pub fn log_malformed_items(&self) -> usize {
+        let malformed = self.channel.malformed_items();
+        for (item, reason) in malformed.iter() {
+            log::warn!(
+                "Skipped a malformed item in feed {:?} ({}): {}. Title: {}",
+                self.uid,
+                self.name,
+                reason,
+                item.title().unwrap_or("no title")
+            );
+        }
+        if !malformed.is_empty() {
+            log::warn!(
+                "Skipped {} malformed items in feed {:?} ({})",
+                malformed.len(),
+                self.uid,
+                self.name
             );
-            if item.guid().is_some() {
-                let guid = ChannelWrapper::parse_guid(item).ok();
-                self.last_pushed_guid = guid;
-                return guid;
-            }
         }
-        log::debug!("Could not find a guid in feed {}", &self.name);
-        None
+        malformed.len()
+    }
+
+    /// Whether the user can find the feed in /feeds.
+    pub fn listed_for(&self, chat_id: i64) -> bool {
+        self.visibility == Visibility::Public || self.owner == Some(chat_id)
+    }
+
+    /// Whether the user can add the feed to a collection.
+    pub fn visible_to(&self, chat_id: i64) -> bool {
+        self.visibility != Visibility::Private || self.owner == Some(chat_id)
     }
 
     pub fn get_link(&self) -> &String {
         &self.link
     }
+    /// Builds a feed from a PubMed journal link, or any other RSS or Atom feed.
+    /// Only PubMed journal feeds get their NLM id as uid.
     // CAVE: a wrong feed can be inserted
     pub fn build_from_link(link: &str, name: &str) -> Result<PubmedFeed, &'static str> {
-        let link = link.to_string();
-        if !link.contains("://pubmed.ncbi.nlm.nih.gov/rss/") {
-            return Err("Link provided is not a valid pubmed RSS feed!");
+        let link = link.trim().to_string();
+        if !link.starts_with("http://") && !link.starts_with("https://") {
+            return Err("Link provided is not a valid http(s) link!");
         }
         let re =
             Regex::new(r"://pubmed.ncbi.nlm.nih.gov/rss/journals/([0-9]+)/.*?limit=([0-9]+).*$")
                 .unwrap();
-        let uid;
-        if let Some(caps) = re.captures(&link) {
-            uid = Some(caps[1].parse::<u32>().unwrap())
-        } else {
-            uid = None
-        };
+        let uid = re
+            .captures(&link)
+            .and_then(|caps| caps[1].parse::<u32>().ok());
         Ok(PubmedFeed {
             name: name.to_string(),
             uid,
             link,
             channel: ChannelWrapper::new(),
-            last_pushed_guid: None,
             subscribers: 0u32,
+            query: None,
+            cursor: None,
+            etag: None,
+            last_modified: None,
+            health: FeedHealth::default(),
+            refresh_interval: None,
+            owner: None,
+            visibility: Visibility::Public,
+        })
+    }
+
+    /// Builds a feed from a PubMed search term, fetched with the E-utilities.
+    pub fn build_from_query(query: &str, name: &str) -> Result<PubmedFeed, &'static str> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err("No search term provided!");
+        }
+        Ok(PubmedFeed {
+            name: name.to_string(),
+            uid: None,
+            link: eutils::search_link(query),
+            channel: ChannelWrapper::new(),
+            subscribers: 0u32,
+            query: Some(query.to_string()),
+            cursor: None,
+            etag: None,
+            last_modified: None,
+            health: FeedHealth::default(),
+            refresh_interval: None,
+            owner: None,
+            visibility: Visibility::Public,
         })
     }
     pub fn key(&self) -> &String {
@@ -270,6 +514,7 @@ impl UserRssList {
             feeds: HashSet::new(),
             whitelist: HashSet::new(),
             blacklist: HashSet::new(),
+            style: MessageStyle::default(),
         }
     }
 
@@ -278,10 +523,19 @@ impl UserRssList {
             && !item_contains_keyword(item, &self.blacklist)
     }
 
+    /// The whitelist keywords found in the item, or None if the item is filtered out.
+    pub fn matched_keywords(&self, item: &Item) -> Option<Vec<String>> {
+        if item_contains_keyword(item, &self.blacklist) {
+            return None;
+        }
+        let matched = matched_keywords(item, &self.whitelist);
+        if matched.is_empty() { None } else { Some(matched) }
+    }
+
     pub fn filter_items<'a>(&self, items: Vec<&'a Item>) -> Vec<&'a Item> {
         items
             .into_iter()
-            .inspect(|item| log::debug!("Title: {}", item.title().unwrap()))
+            .inspect(|item| log::debug!("Title: {}", item.title().unwrap_or("")))
             .filter(|item| item_contains_keyword(item, &self.whitelist))
             // .inspect(|item| log::debug!("item passed whitelist: {}", item.title().unwrap()))
             .filter(|item| !item_contains_keyword(item, &self.blacklist))
@@ -384,6 +638,7 @@ impl ChannelLookupTable {
 #[cfg(test)]
 mod tests {
     use crate::preset::{self, Keywords};
+    use crate::testserver::{self, Response};
 
     use super::*;
 
@@ -398,12 +653,82 @@ mod tests {
             full_name: None,
             last_pushed: "31 sept 2024".to_string(),
             rss_lists: vec![uro_rss_list],
+            links: Some(vec!["PubMed".to_string()]),
+            backlog: 5,
         };
         println!("{:?}", &user);
         let cloned_json = user.to_json().unwrap();
         assert_eq!(User::build_from_json(&cloned_json).unwrap(), user);
     }
 
+    #[tokio::test]
+    async fn conditional_download() {
+        let base_url = testserver::serve(2, |request| {
+            if request.to_lowercase().contains("if-none-match: \"v1\"") {
+                assert!(
+                    request
+                        .to_lowercase()
+                        .contains("if-modified-since: tue, 15 apr 2025 10:00:00 gmt")
+                );
+                Response::status(304)
+            } else {
+                Response::ok(
+                    r#"<rss version="2.0"><channel><title>Journal</title><link>https://journal.example.org</link><description></description>
+                    <item><title>An article</title><guid>https://journal.example.org/1</guid></item>
+                    </channel></rss>"#,
+                )
+                .header("ETag", "\"v1\"")
+                .header("Last-Modified", "Tue, 15 Apr 2025 10:00:00 GMT")
+            }
+        });
+        let mut feed = PubmedFeed::build_from_link(&format!("{base_url}/rss"), "Journal").unwrap();
+        feed.subscribers = 1;
+
+        assert_eq!(
+            feed.update_channel_limited().await.unwrap(),
+            ChannelUpdate::Modified
+        );
+        assert_eq!(feed.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(feed.channel.items().len(), 1);
+
+        // 304: the channel is kept
+        assert_eq!(
+            feed.update_channel_limited().await.unwrap(),
+            ChannelUpdate::NotModified
+        );
+        assert_eq!(feed.channel.items().len(), 1);
+        assert_eq!(
+            feed.last_modified.as_deref(),
+            Some("Tue, 15 Apr 2025 10:00:00 GMT")
+        );
+    }
+
+    #[test]
+    fn refresh_interval() {
+        let json = std::fs::read_to_string("test/channel_radiology.json").unwrap();
+        let mut feed = PubmedFeed::build_from_link(
+            "https://pubmed.ncbi.nlm.nih.gov/rss/journals/0401260/?limit=15",
+            "Radiology",
+        )
+        .unwrap();
+        assert_eq!(feed.learned_refresh_interval(), TimeDelta::hours(1));
+        assert!(feed.is_due(Local::now()));
+
+        // A weekly journal
+        feed.channel = ChannelWrapper::from_json(&json).unwrap();
+        assert_eq!(feed.channel.average_item_gap(), Some(TimeDelta::days(7)));
+        assert_eq!(feed.refresh_interval(), TimeDelta::hours(24));
+
+        let now = Local::now();
+        feed.health.record_success(now - TimeDelta::hours(12), 15, true);
+        assert!(!feed.is_due(now));
+        // The update on the hour after a day is not skipped
+        assert!(feed.is_due(now + TimeDelta::hours(12) - TimeDelta::minutes(1)));
+
+        feed.refresh_interval = Some(6 * 60);
+        assert!(feed.is_due(now));
+    }
+
     #[test]
     fn feed_contains_test() {
         let journal1 = PubmedFeed {
@@ -411,22 +736,43 @@ mod tests {
             link: "https://pubmed.ncbi.nlm.nih.gov/rss/journals/101532453/?limit=5&name=Insights%20Imaging&utm_campaign=journals".to_string(),
 	    uid: Some(101532453),
 	    channel: ChannelWrapper::new(),
-            last_pushed_guid: None,
-            subscribers: 0};
+            subscribers: 0,
+            query: None,
+            cursor: None,
+            etag: None,
+            last_modified: None,
+            health: FeedHealth::default(),
+            refresh_interval: None,
+            owner: None,
+            visibility: Visibility::Public};
         let journal2 = PubmedFeed {
 	    name: "something else".to_string(),
             link: "https://pubmed.ncbi.nlm.nih.gov/rss/journals/101532454/?limit=5&utm_campaign=journals".to_string(),
 	    uid: Some(100000),
 	    channel: ChannelWrapper::new(),
-            last_pushed_guid: None,
-            subscribers: 0};
+            subscribers: 0,
+            query: None,
+            cursor: None,
+            etag: None,
+            last_modified: None,
+            health: FeedHealth::default(),
+            refresh_interval: None,
+            owner: None,
+            visibility: Visibility::Public};
         let journal11 = PubmedFeed {
 	    name: "something else".to_string(),
             link: "https://pubmed.ncbi.nlm.nih.gov/rss/journals/101532453/?limit=5&name=Insights%20Imaging&utm_campaign=journals".to_string(),
 	    uid: Some(101532453),
 	    channel: ChannelWrapper::new(),
-            last_pushed_guid: None,
-            subscribers: 0};
+            subscribers: 0,
+            query: None,
+            cursor: None,
+            etag: None,
+            last_modified: None,
+            health: FeedHealth::default(),
+            refresh_interval: None,
+            owner: None,
+            visibility: Visibility::Public};
         assert_eq!(journal1, journal11);
         let vec = vec![journal1, journal2];
         assert!(vec.contains(&journal11));
@@ -1,18 +1,26 @@
-const DB_VERSION: u32 = 2;
+const DB_VERSION: u32 = 13;
+/// Seen items are forgotten after they have not been in their feed for this long.
+const SEEN_RETENTION_DAYS: i64 = 60;
 
 pub mod sqlite {
-    use crate::db::DB_VERSION;
+    use crate::db::{DB_VERSION, SEEN_RETENTION_DAYS};
     use std::collections::{HashMap, HashSet};
+    use std::str::FromStr;
     use std::sync::Arc;
 
-    use rusqlite::{Connection, DatabaseName, Result, params};
+    use chrono::Local;
+
+    use rusqlite::{Connection, DatabaseName, OptionalExtension, Result, params};
     use teloxide::RequestError;
     use tokio_rusqlite;
     // use tokio_rusqlite;
+    use crate::archive::{Article, fts_query};
     use crate::channelwrapper::ChannelWrapper;
-    use crate::datastructs::User;
-    use crate::datastructs::{PubmedFeed, UserRssList};
+    use crate::datastructs::{DEFAULT_BACKLOG, User};
+    use crate::datastructs::{PubmedFeed, UserRssList, Visibility};
+    use crate::health::FeedHealth;
     use crate::make_feedlist;
+    use crate::refresh::{refresh_feeds, refresh_settings};
 
     pub fn open(path: &str) -> Result<Connection> {
         let conn = Connection::open(path)?;
@@ -38,7 +46,9 @@ pub mod sqlite {
             id           INTEGER PRIMARY KEY,
             full_name    TEXT,
             last_pushed  TEXT NOT NULL,
-            collections  TEXT NOT NULL
+            collections  TEXT NOT NULL,
+            links        TEXT,
+            backlog      INTEGER
         )",
             (), // empty list of parameters.
         )?;
@@ -48,11 +58,27 @@ pub mod sqlite {
             name          TEXT NOT NULL,
             link          TEXT NOT NULL UNIQUE,
             channel       TEXT NOT NULL,
-            last_pushed_guid   INTEGER,
-            subscribers   INTEGER
+            subscribers   INTEGER,
+            query         TEXT,
+            cursor        TEXT,
+            etag          TEXT,
+            last_modified TEXT,
+            last_success  TEXT,
+            failures      INTEGER,
+            last_error    TEXT,
+            last_failure  TEXT,
+            item_count    INTEGER,
+            last_new_item TEXT,
+            refresh_interval INTEGER,
+            owner         INTEGER,
+            visibility    TEXT,
+            malformed_items INTEGER
         )",
             (), // empty list of parameters.
         )?;
+        create_seen_items(conn)?;
+        create_articles(conn)?;
+        create_articles_fts(conn)?;
         conn.pragma_update(Some(DatabaseName::Main), "user_version", DB_VERSION)?;
 
         for feed in make_feedlist() {
@@ -113,19 +139,33 @@ pub mod sqlite {
 
             log::info!("Updating the channel column...");
             let mut stmt =
-                conn.prepare("SELECT id, name, link, last_pushed_guid, subscribers FROM feeds")?;
+                conn.prepare("SELECT id, name, link, subscribers FROM feeds")?;
             let feed_iter = stmt.query_map([], |row| {
                 Ok(PubmedFeed {
                     name: row.get(1)?,
                     uid: Some(row.get(0)?),
                     link: row.get(2)?,
                     channel: ChannelWrapper::new(),
-                    last_pushed_guid: row.get(3)?,
-                    subscribers: row.get(4).unwrap_or(0),
+                    subscribers: row.get(3).unwrap_or(0),
+                    query: None,
+                    cursor: None,
+                    etag: None,
+                    last_modified: None,
+                    health: FeedHealth::default(),
+                    refresh_interval: None,
+                    owner: None,
+                    visibility: Visibility::Public,
                 })
             })?;
+            // Not update_feed: the columns of the later versions do not exist yet
             for feed in feed_iter {
-                update_feed(conn, &feed?)?;
+                let feed = feed?;
+                let channel = serde_json::to_string(&feed.channel)
+                    .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?;
+                conn.execute(
+                    "UPDATE feeds SET channel = ?1, subscribers = ?2 WHERE id = ?3",
+                    params![&channel, &feed.subscribers, &feed.uid],
+                )?;
             }
             log::info!("Update to db version 1 complete.");
             version = 1;
@@ -140,7 +180,183 @@ pub mod sqlite {
                 (), // empty list of parameters.
             )?;
             log::info!("Update to db version 2 complete.");
-            // version = 2;
+            version = 2;
+        }
+        // Update from 2 to 3
+        if version == 2 {
+            log::info!("Migrating to db version 3...");
+            log::info!("Adding links column...");
+            conn.execute(
+                "ALTER TABLE users
+                   ADD links        TEXT;",
+                (), // empty list of parameters.
+            )?;
+            log::info!("Update to db version 3 complete.");
+            version = 3;
+        }
+        // Update from 3 to 4
+        if version == 3 {
+            log::info!("Migrating to db version 4...");
+            log::info!("Adding query and cursor columns...");
+            conn.execute(
+                "ALTER TABLE feeds
+                   ADD query        TEXT;",
+                (), // empty list of parameters.
+            )?;
+            conn.execute(
+                "ALTER TABLE feeds
+                   ADD cursor       TEXT;",
+                (), // empty list of parameters.
+            )?;
+            log::info!("Update to db version 4 complete.");
+            version = 4;
+        }
+        // Update from 4 to 5
+        if version == 4 {
+            log::info!("Migrating to db version 5...");
+            log::info!("Adding etag and last_modified columns...");
+            conn.execute(
+                "ALTER TABLE feeds
+                   ADD etag          TEXT;",
+                (), // empty list of parameters.
+            )?;
+            conn.execute(
+                "ALTER TABLE feeds
+                   ADD last_modified TEXT;",
+                (), // empty list of parameters.
+            )?;
+            log::info!("Update to db version 5 complete.");
+            version = 5;
+        }
+        // Update from 5 to 6
+        if version == 5 {
+            log::info!("Migrating to db version 6...");
+            log::info!("Adding the feed health columns...");
+            for column in [
+                "last_success  TEXT",
+                "failures      INTEGER",
+                "last_error    TEXT",
+                "last_failure  TEXT",
+                "item_count    INTEGER",
+                "last_new_item TEXT",
+            ] {
+                conn.execute(&format!("ALTER TABLE feeds ADD {column};"), ())?;
+            }
+            log::info!("Update to db version 6 complete.");
+            version = 6;
+        }
+        // Update from 6 to 7
+        if version == 6 {
+            log::info!("Migrating to db version 7...");
+            log::info!("Adding refresh_interval column...");
+            conn.execute(
+                "ALTER TABLE feeds
+                   ADD refresh_interval INTEGER;",
+                (), // empty list of parameters.
+            )?;
+            log::info!("Update to db version 7 complete.");
+            version = 7;
+        }
+        // Update from 7 to 8
+        if version == 7 {
+            log::info!("Migrating to db version 8...");
+            log::info!("Adding seen_items table...");
+            create_seen_items(conn)?;
+            log::info!("Marking the items up to last_pushed_guid as seen...");
+            let now = Local::now().timestamp();
+            let mut stmt = conn.prepare("SELECT id, channel, last_pushed_guid FROM feeds")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    row.get::<_, ChannelWrapper>(1)?,
+                    row.get::<_, Option<u32>>(2)?,
+                ))
+            })?;
+            for row in rows {
+                let (id, channel, last_pushed_guid) = row?;
+                let ids: Vec<u32> = channel
+                    .item_ids()
+                    .into_iter()
+                    .map(|(_, item_id)| item_id)
+                    // Only PubMed ids are ordered, the items of other feeds were all sent
+                    .filter(|item_id| match last_pushed_guid {
+                        Some(guid) if channel.is_pubmed() => *item_id <= guid,
+                        _ => true,
+                    })
+                    .collect();
+                mark_seen(conn, id, &ids, now)?;
+            }
+            log::info!("Update to db version 8 complete.");
+            version = 8;
+        }
+        // Update from 8 to 9
+        if version == 8 {
+            log::info!("Migrating to db version 9...");
+            log::info!("Adding articles tables...");
+            create_articles(conn)?;
+            log::info!("Archiving the items of the stored channels...");
+            // Not get_feeds: the columns of the later versions do not exist yet
+            let now = Local::now().timestamp();
+            let mut stmt = conn.prepare("SELECT id, channel FROM feeds")?;
+            let channels = stmt
+                .query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, ChannelWrapper>(1)?)))?
+                .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+            let tx = conn.unchecked_transaction()?;
+            let mut archived = 0;
+            for (id, channel) in channels {
+                for article in channel.items().iter().filter_map(Article::from_item) {
+                    archive_article(&tx, id, &article, now)?;
+                    archived += 1;
+                }
+            }
+            tx.commit()?;
+            log::info!("Archived {} items.", archived);
+            log::info!("Update to db version 9 complete.");
+            version = 9;
+        }
+        // Update from 9 to 10
+        if version == 9 {
+            log::info!("Migrating to db version 10...");
+            log::info!("Adding articles_fts search index...");
+            create_articles_fts(conn)?;
+            conn.execute("INSERT INTO articles_fts(articles_fts) VALUES('rebuild')", ())?;
+            log::info!("Update to db version 10 complete.");
+            version = 10;
+        }
+        // Update from 10 to 11
+        if version == 10 {
+            log::info!("Migrating to db version 11...");
+            log::info!("Adding backlog column...");
+            conn.execute(
+                "ALTER TABLE users
+                   ADD backlog INTEGER;",
+                (), // empty list of parameters.
+            )?;
+            log::info!("Update to db version 11 complete.");
+            version = 11;
+        }
+        // Update from 11 to 12
+        if version == 11 {
+            log::info!("Migrating to db version 12...");
+            log::info!("Adding owner and visibility columns...");
+            // The existing feeds stay public
+            for column in ["owner INTEGER", "visibility TEXT"] {
+                conn.execute(&format!("ALTER TABLE feeds ADD {column};"), ())?;
+            }
+            log::info!("Update to db version 12 complete.");
+            version = 12;
+        }
+        // Update from 12 to 13
+        if version == 12 {
+            log::info!("Migrating to db version 13...");
+            log::info!("Adding malformed_items column...");
+            conn.execute(
+                "ALTER TABLE feeds
+                   ADD malformed_items INTEGER;",
+                (), // empty list of parameters.
+            )?;
+            log::info!("Update to db version 13 complete.");
+            // version = 13;
         }
 
         log::info!("Done. Updating db_version");
@@ -178,6 +394,8 @@ pub mod sqlite {
 
     pub fn update_subscribers(conn: &Connection) -> Result<(), rusqlite::Error> {
         let users = get_users(conn)?;
+        // The feeds that are in no collection anymore
+        conn.execute("UPDATE feeds SET subscribers = 0", ())?;
         let mut map: HashMap<u32, u32> = HashMap::new();
         for user in users {
             for collection in user.rss_lists {
@@ -195,29 +413,48 @@ pub mod sqlite {
     pub fn add_user(conn: &Connection, user: &User) -> Result<usize, rusqlite::Error> {
         let collections = serde_json::to_string(&user.rss_lists)
             .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?;
+        let links = links_to_json(user)?;
         conn.execute(
-            "INSERT OR IGNORE INTO users (id, full_name, last_pushed, collections) VALUES (?1, ?2, ?3, ?4)",
-            (&user.chat_id, &user.full_name, &user.last_pushed, &collections),
+            "INSERT OR IGNORE INTO users (id, full_name, last_pushed, collections, links, backlog) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (&user.chat_id, &user.full_name, &user.last_pushed, &collections, &links, &user.backlog),
         )
     }
 
     pub fn update_user(conn: &Connection, user: &User) -> Result<usize, rusqlite::Error> {
         let collections = serde_json::to_string(&user.rss_lists)
             .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?;
+        let links = links_to_json(user)?;
         log::debug!("Updating user {} in the database", user.chat_id);
         conn.execute(
             "UPDATE users
              SET last_pushed = ?1,
                  collections = ?2,
-                 full_name = ?3
-             WHERE id = ?4",
-            params![&user.last_pushed, &collections, &user.full_name, &user.chat_id],
+                 full_name = ?3,
+                 links = ?4,
+                 backlog = ?5
+             WHERE id = ?6",
+            params![&user.last_pushed, &collections, &user.full_name, &links, &user.backlog, &user.chat_id],
         )
     }
 
+    fn links_to_json(user: &User) -> Result<Option<String>, rusqlite::Error> {
+        user.links
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))
+    }
+
+    fn links_from_json(links: Option<String>) -> Result<Option<Vec<String>>, rusqlite::Error> {
+        links
+            .map(|s| serde_json::from_str(s.as_str()))
+            .transpose()
+            .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))
+    }
+
     pub fn get_user(conn: &Connection, id: i64) -> Result<Option<User>, rusqlite::Error> {
         let mut stmt =
-            conn.prepare("SELECT id, full_name, last_pushed, collections FROM users WHERE id=(?1)")?;
+            conn.prepare("SELECT id, full_name, last_pushed, collections, links, backlog FROM users WHERE id=(?1)")?;
         let mut rows = stmt.query([id])?;
         let row_opt = rows.next()?;
         if let Some(row) = row_opt {
@@ -230,6 +467,8 @@ pub mod sqlite {
                     serde_json::from_str(s.as_str())
                         .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?
                 },
+                links: links_from_json(row.get(4)?)?,
+                backlog: row.get::<_, Option<u32>>(5)?.unwrap_or(DEFAULT_BACKLOG),
             }))
         } else {
             Ok(None)
@@ -237,7 +476,7 @@ pub mod sqlite {
     }
 
     pub fn get_users(conn: &Connection) -> Result<Vec<User>, rusqlite::Error> {
-        let mut stmt = conn.prepare("SELECT id, full_name, last_pushed, collections FROM users")?;
+        let mut stmt = conn.prepare("SELECT id, full_name, last_pushed, collections, links, backlog FROM users")?;
         let user_iter = stmt.query_map([], |row| {
             Ok(User {
                 chat_id: row.get(0)?,
@@ -248,6 +487,8 @@ pub mod sqlite {
                     serde_json::from_str(s.as_str())
                         .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?
                 },
+                links: links_from_json(row.get(4)?)?,
+                backlog: row.get::<_, Option<u32>>(5)?.unwrap_or(DEFAULT_BACKLOG),
             })
         })?;
         user_iter
@@ -260,8 +501,8 @@ pub mod sqlite {
             .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?;
         if feed.uid.is_some() {
             conn.execute(
-                "INSERT OR IGNORE INTO feeds (id, name, link, channel, last_pushed_guid, subscribers) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                (&feed.uid.unwrap(), &feed.name, &feed.link, &channel, &feed.last_pushed_guid, &feed.subscribers),
+                "INSERT OR IGNORE INTO feeds (id, name, link, channel, subscribers, query, cursor, etag, last_modified, owner, visibility) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                (&feed.uid.unwrap(), &feed.name, &feed.link, &channel, &feed.subscribers, &feed.query, &feed.cursor, &feed.etag, &feed.last_modified, &feed.owner, &feed.visibility.to_string()),
             )?;
             Ok(feed.uid.unwrap())
         } else {
@@ -271,8 +512,8 @@ pub mod sqlite {
                 &feed.link
             );
             conn.execute(
-                "INSERT OR IGNORE INTO feeds (name, link, channel, last_pushed_guid, subscribers) VALUES (?1, ?2, ?3, ?4, ?5)",
-                (&feed.name, &feed.link, &channel, &feed.last_pushed_guid, &feed.subscribers),
+                "INSERT OR IGNORE INTO feeds (name, link, channel, subscribers, query, cursor, etag, last_modified, owner, visibility) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                (&feed.name, &feed.link, &channel, &feed.subscribers, &feed.query, &feed.cursor, &feed.etag, &feed.last_modified, &feed.owner, &feed.visibility.to_string()),
             )?;
 
             let mut stmt = conn.prepare("SELECT id FROM feeds WHERE link=(?1)")?;
@@ -284,27 +525,274 @@ pub mod sqlite {
         }
     }
 
-    pub fn update_guid_feeds(
+    fn create_seen_items(conn: &Connection) -> Result<usize, rusqlite::Error> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS seen_items (
+            feed_id       INTEGER NOT NULL,
+            item_id       INTEGER NOT NULL,
+            last_seen     INTEGER NOT NULL,
+            PRIMARY KEY (feed_id, item_id)
+        )",
+            (), // empty list of parameters.
+        )
+    }
+
+    fn create_articles(conn: &Connection) -> Result<(), rusqlite::Error> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS articles (
+            id            INTEGER PRIMARY KEY AUTOINCREMENT,
+            key           TEXT NOT NULL UNIQUE,
+            pmid          INTEGER,
+            doi           TEXT,
+            title         TEXT NOT NULL,
+            journal       TEXT,
+            authors       TEXT NOT NULL,
+            abstract      TEXT,
+            link          TEXT,
+            published     TEXT,
+            first_seen    INTEGER NOT NULL,
+            last_seen     INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS articles_pmid ON articles (pmid);
+        CREATE INDEX IF NOT EXISTS articles_doi ON articles (doi);
+        CREATE TABLE IF NOT EXISTS article_feeds (
+            article_id    INTEGER NOT NULL,
+            feed_id       INTEGER NOT NULL,
+            PRIMARY KEY (article_id, feed_id)
+        );",
+        )
+    }
+
+    /// The search index of the titles and abstracts, kept up to date with triggers.
+    fn create_articles_fts(conn: &Connection) -> Result<(), rusqlite::Error> {
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS articles_fts USING fts5(
+            title, abstract, content='articles', content_rowid='id'
+        );
+        CREATE TRIGGER IF NOT EXISTS articles_ai AFTER INSERT ON articles BEGIN
+            INSERT INTO articles_fts(rowid, title, abstract) VALUES (new.id, new.title, new.abstract);
+        END;
+        CREATE TRIGGER IF NOT EXISTS articles_ad AFTER DELETE ON articles BEGIN
+            INSERT INTO articles_fts(articles_fts, rowid, title, abstract) VALUES('delete', old.id, old.title, old.abstract);
+        END;
+        CREATE TRIGGER IF NOT EXISTS articles_au AFTER UPDATE ON articles BEGIN
+            INSERT INTO articles_fts(articles_fts, rowid, title, abstract) VALUES('delete', old.id, old.title, old.abstract);
+            INSERT INTO articles_fts(rowid, title, abstract) VALUES (new.id, new.title, new.abstract);
+        END;",
+        )
+    }
+
+    /// Adds the article, or updates it when an article with the same PMID, doi
+    /// or key is stored, and links it to the feed. Returns the id of the article.
+    pub fn archive_article(
         conn: &Connection,
-        feeds: &[PubmedFeed],
-    ) -> Result<(), rusqlite::Error> {
-        // Applies everything, but does not interrupt when there is an error
-        let result: Result<Vec<()>, rusqlite::Error> = feeds
-            .iter()
-            .map(|feed| update_guid_feed(conn, feed))
-            .collect();
-        result.map(|_| ())
+        feed_id: u32,
+        article: &Article,
+        now: i64,
+    ) -> Result<i64, rusqlite::Error> {
+        let authors = serde_json::to_string(&article.authors)
+            .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?;
+        let existing: Option<i64> = conn
+            .prepare_cached(
+                "SELECT id FROM articles WHERE pmid = ?1 OR doi = ?2 OR key = ?3 ORDER BY id LIMIT 1",
+            )?
+            .query_row(params![&article.pmid, &article.doi, &article.key], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        let id = match existing {
+            Some(id) => {
+                // Keeps the identifiers that are missing from this feed
+                conn.prepare_cached(
+                    "UPDATE articles
+                     SET pmid = COALESCE(?2, pmid),
+                         doi = COALESCE(?3, doi),
+                         title = ?4,
+                         journal = COALESCE(?5, journal),
+                         authors = CASE WHEN ?6 = '[]' THEN authors ELSE ?6 END,
+                         abstract = COALESCE(?7, abstract),
+                         link = COALESCE(?8, link),
+                         published = COALESCE(?9, published),
+                         last_seen = ?10
+                     WHERE id = ?1",
+                )?
+                .execute(params![
+                    id,
+                    &article.pmid,
+                    &article.doi,
+                    &article.title,
+                    &article.journal,
+                    &authors,
+                    &article.abstract_text,
+                    &article.link,
+                    &article.published,
+                    now,
+                ])?;
+                id
+            }
+            None => {
+                conn.prepare_cached(
+                    "INSERT INTO articles (key, pmid, doi, title, journal, authors, abstract, link, published, first_seen, last_seen)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?10)",
+                )?
+                .execute(params![
+                    &article.key,
+                    &article.pmid,
+                    &article.doi,
+                    &article.title,
+                    &article.journal,
+                    &authors,
+                    &article.abstract_text,
+                    &article.link,
+                    &article.published,
+                    now,
+                ])?;
+                conn.last_insert_rowid()
+            }
+        };
+        conn.prepare_cached(
+            "INSERT OR IGNORE INTO article_feeds (article_id, feed_id) VALUES (?1, ?2)",
+        )?
+        .execute(params![id, feed_id])?;
+        Ok(id)
     }
 
-    pub fn update_guid_feed(conn: &Connection, feed: &PubmedFeed) -> Result<(), rusqlite::Error> {
-        let mut stmt = conn.prepare_cached(
-            "UPDATE feeds
-                 SET last_pushed_guid = ?1
-                 WHERE id = ?2",
+    /// Archives the items of the channels of the feeds in one transaction.
+    /// Returns the number of archived items.
+    pub fn archive_feeds(conn: &Connection, feeds: &[&PubmedFeed]) -> Result<usize, rusqlite::Error> {
+        let now = Local::now().timestamp();
+        let tx = conn.unchecked_transaction()?;
+        let mut archived = 0;
+        for feed in feeds {
+            let Some(uid) = feed.uid else { continue };
+            for article in feed.channel.items().iter().filter_map(Article::from_item) {
+                archive_article(&tx, uid, &article, now)?;
+                archived += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(archived)
+    }
+
+    fn article_from_row(row: &rusqlite::Row) -> Result<Article, rusqlite::Error> {
+        let authors: String = row.get(6)?;
+        Ok(Article {
+            key: row.get(1)?,
+            pmid: row.get(2)?,
+            doi: row.get(3)?,
+            title: row.get(4)?,
+            journal: row.get(5)?,
+            authors: serde_json::from_str(&authors).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(6, rusqlite::types::Type::Text, err.into())
+            })?,
+            abstract_text: row.get(7)?,
+            link: row.get(8)?,
+            published: row.get(9)?,
+        })
+    }
+
+    const ARTICLE_COLUMNS: &str = "id, key, pmid, doi, title, journal, authors, abstract, link, published";
+
+    pub fn get_article(conn: &Connection, id: i64) -> Result<Option<Article>, rusqlite::Error> {
+        conn.prepare(&format!("SELECT {ARTICLE_COLUMNS} FROM articles WHERE id = ?1"))?
+            .query_row([id], article_from_row)
+            .optional()
+    }
+
+    /// The articles that match the search (see `archive::fts_query`), best
+    /// matches first, with the total number of matches. Matches in the title
+    /// weigh more than in the abstract. Only the articles of feeds that are not
+    /// private, or are owned by the user, are searched.
+    pub fn search_articles(
+        conn: &Connection,
+        chat_id: i64,
+        search: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<(Vec<Article>, usize), rusqlite::Error> {
+        let Some(query) = fts_query(search) else {
+            return Ok((Vec::new(), 0));
+        };
+        let matches = "FROM articles
+             JOIN (SELECT rowid, bm25(articles_fts, 10.0, 1.0) AS rank
+                   FROM articles_fts WHERE articles_fts MATCH ?1) AS matches
+             ON articles.id = matches.rowid
+             WHERE EXISTS (SELECT 1 FROM article_feeds JOIN feeds ON feeds.id = article_feeds.feed_id
+                           WHERE article_feeds.article_id = articles.id
+                           AND (feeds.visibility IS NOT 'private' OR feeds.owner = ?2))";
+        let total: usize = conn.query_row(
+            &format!("SELECT count(*) {matches}"),
+            params![&query, chat_id],
+            |row| row.get(0),
         )?;
-        stmt.execute(params![&feed.last_pushed_guid, &feed.uid.unwrap(),])?;
-        log::debug!("Updated last_pushed_guid of feed {}", &feed.name);
-        Ok(())
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ARTICLE_COLUMNS} {matches}
+             ORDER BY matches.rank, articles.first_seen DESC
+             LIMIT ?3 OFFSET ?4"
+        ))?;
+        let articles = stmt
+            .query_map(params![&query, chat_id, limit, offset], article_from_row)?
+            .collect::<Result<Vec<Article>, rusqlite::Error>>()?;
+        Ok((articles, total))
+    }
+
+    /// The articles first downloaded at or after `since` (a unix timestamp), oldest
+    /// first, with the id of each feed they appeared in.
+    pub fn get_articles_since(
+        conn: &Connection,
+        since: i64,
+    ) -> Result<Vec<(u32, Article)>, rusqlite::Error> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ARTICLE_COLUMNS}, article_feeds.feed_id FROM articles
+             JOIN article_feeds ON articles.id = article_feeds.article_id
+             WHERE articles.first_seen >= ?1
+             ORDER BY articles.first_seen, articles.id"
+        ))?;
+        let articles = stmt.query_map([since], |row| Ok((row.get(10)?, article_from_row(row)?)))?;
+        articles.collect()
+    }
+
+    /// The ids of the feeds the article appeared in.
+    pub fn get_article_feeds(conn: &Connection, id: i64) -> Result<Vec<u32>, rusqlite::Error> {
+        let mut stmt =
+            conn.prepare("SELECT feed_id FROM article_feeds WHERE article_id = ?1 ORDER BY feed_id")?;
+        let ids = stmt.query_map([id], |row| row.get(0))?;
+        ids.collect()
+    }
+
+    /// The ids (see `ChannelWrapper::item_ids`) of the items of the feed that were already sent.
+    pub fn get_seen_items(conn: &Connection, feed_id: u32) -> Result<HashSet<u32>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached("SELECT item_id FROM seen_items WHERE feed_id = ?1")?;
+        let ids = stmt.query_map([feed_id], |row| row.get(0))?;
+        ids.collect()
+    }
+
+    /// Marks the items as seen at `now` (a unix timestamp), also the ones that were seen before.
+    pub fn mark_seen(
+        conn: &Connection,
+        feed_id: u32,
+        item_ids: &[u32],
+        now: i64,
+    ) -> Result<(), rusqlite::Error> {
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO seen_items (feed_id, item_id, last_seen) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (feed_id, item_id) DO UPDATE SET last_seen = ?3",
+            )?;
+            for item_id in item_ids {
+                stmt.execute(params![feed_id, item_id, now])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Forgets the items that have not been in their feed since `SEEN_RETENTION_DAYS` before `now`.
+    pub fn prune_seen_items(conn: &Connection, now: i64) -> Result<usize, rusqlite::Error> {
+        let before = now - SEEN_RETENTION_DAYS * 24 * 60 * 60;
+        let pruned = conn.execute("DELETE FROM seen_items WHERE last_seen < ?1", [before])?;
+        log::debug!("Pruned {} seen items", pruned);
+        Ok(pruned)
     }
 
     pub fn update_feed(conn: &Connection, feed: &PubmedFeed) -> Result<u32, rusqlite::Error> {
@@ -317,8 +805,21 @@ pub mod sqlite {
                      name = ?2,
                      link = ?3,
                      channel = ?4,
-                     last_pushed_guid = ?5,
-                     subscribers = ?6
+                     subscribers = ?5,
+                     query = ?6,
+                     cursor = ?7,
+                     etag = ?8,
+                     last_modified = ?9,
+                     last_success = ?10,
+                     failures = ?11,
+                     last_error = ?12,
+                     last_failure = ?13,
+                     item_count = ?14,
+                     last_new_item = ?15,
+                     refresh_interval = ?16,
+                     owner = ?17,
+                     visibility = ?18,
+                     malformed_items = ?19
                  WHERE id = ?1",
             )?;
             stmt.execute(params![
@@ -326,8 +827,21 @@ pub mod sqlite {
                 &feed.name,
                 &feed.link,
                 &channel,
-                &feed.last_pushed_guid,
                 &feed.subscribers,
+                &feed.query,
+                &feed.cursor,
+                &feed.etag,
+                &feed.last_modified,
+                &feed.health.last_success,
+                &feed.health.failures,
+                &feed.health.last_error,
+                &feed.health.last_failure,
+                &feed.health.item_count,
+                &feed.health.last_new_item,
+                &feed.refresh_interval,
+                &feed.owner,
+                &feed.visibility.to_string(),
+                &feed.health.malformed_items,
             ])?;
             Ok(feed.uid.unwrap())
         } else {
@@ -337,64 +851,185 @@ pub mod sqlite {
         }
     }
 
+    /// Saves the downloaded channels and the health of the feeds in one
+    /// transaction. Only the columns set by the download are written: the
+    /// name, subscribers, interval, owner and visibility may have been changed
+    /// by commands during the download. Feeds that were deleted or got
+    /// another link in the meantime are skipped. Returns the number of saved feeds.
+    pub fn save_downloads(conn: &Connection, feeds: &[&PubmedFeed]) -> Result<u32, rusqlite::Error> {
+        let tx = conn.unchecked_transaction()?;
+        let mut saved = 0;
+        {
+            let mut stmt = tx.prepare_cached(
+                "UPDATE feeds
+                 SET channel = ?3,
+                     cursor = ?4,
+                     etag = ?5,
+                     last_modified = ?6,
+                     last_success = ?7,
+                     failures = ?8,
+                     last_error = ?9,
+                     last_failure = ?10,
+                     item_count = ?11,
+                     last_new_item = ?12,
+                     malformed_items = ?13
+                 WHERE id = ?1 AND link = ?2",
+            )?;
+            for feed in feeds {
+                let channel = serde_json::to_string(&feed.channel)
+                    .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?;
+                saved += stmt.execute(params![
+                    &feed.uid,
+                    &feed.link,
+                    &channel,
+                    &feed.cursor,
+                    &feed.etag,
+                    &feed.last_modified,
+                    &feed.health.last_success,
+                    &feed.health.failures,
+                    &feed.health.last_error,
+                    &feed.health.last_failure,
+                    &feed.health.item_count,
+                    &feed.health.last_new_item,
+                    &feed.health.malformed_items,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(saved as u32)
+    }
+
+    /// Saves only the health of the feeds in one transaction, eg. after a
+    /// failed download or one that was not modified. Like `save_downloads`,
+    /// feeds that were deleted or got another link are skipped.
+    pub fn save_health(conn: &Connection, feeds: &[&PubmedFeed]) -> Result<u32, rusqlite::Error> {
+        let tx = conn.unchecked_transaction()?;
+        let mut saved = 0;
+        {
+            let mut stmt = tx.prepare_cached(
+                "UPDATE feeds
+                 SET last_success = ?3,
+                     failures = ?4,
+                     last_error = ?5,
+                     last_failure = ?6,
+                     item_count = ?7,
+                     last_new_item = ?8,
+                     malformed_items = ?9
+                 WHERE id = ?1 AND link = ?2",
+            )?;
+            for feed in feeds {
+                saved += stmt.execute(params![
+                    &feed.uid,
+                    &feed.link,
+                    &feed.health.last_success,
+                    &feed.health.failures,
+                    &feed.health.last_error,
+                    &feed.health.last_failure,
+                    &feed.health.item_count,
+                    &feed.health.last_new_item,
+                    &feed.health.malformed_items,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(saved as u32)
+    }
+
+    /// Downloads all feeds and saves the changed ones. Returns the number of changed feeds.
     pub async fn update_channels(conn: &Connection) -> Result<u32, rusqlite::Error> {
         log::info!("Updating all channels...");
         let mut feeds = get_feeds(conn)?;
-        let mut result = Vec::new();
-        let mut acc = 0;
-        for feed in feeds.iter_mut() {
-            result.push(feed.update_channel_limited().await);
-            let r = update_feed(conn, feed);
-            match r {
-                Ok(i) => acc += i,
-                // TODO
-                _ => log::error!("Error updating the database with the updated feed:{:?}", r),
-            }
-        }
-        for r in result {
-            if let Err(e) = r {
-                log::error!("Error in update_channel_limited function:\n{:?}", e)
+        let refreshed = refresh_feeds(&mut feeds, refresh_settings()).await;
+        let modified: Vec<&PubmedFeed> = refreshed.channels.iter().map(|index| &feeds[*index]).collect();
+        let health: Vec<&PubmedFeed> = refreshed.health.iter().map(|index| &feeds[*index]).collect();
+        let updated = save_downloads(conn, &modified)?;
+        save_health(conn, &health)?;
+        archive_feeds(conn, &modified)?;
+        log::info!("Updated {} of {} channels.", updated, feeds.len());
+        Ok(updated)
+    }
+
+    /// Like `update_channels`, but the connection is only used to read and save
+    /// the feeds, not while downloading.
+    pub async fn update_channels_async(
+        conn: &tokio_rusqlite::Connection,
+    ) -> Result<u32, tokio_rusqlite::Error> {
+        log::info!("Updating all channels...");
+        let mut feeds = conn.call(|conn| Ok(get_feeds(conn)?)).await?;
+        let refreshed = refresh_feeds(&mut feeds, refresh_settings()).await;
+        let total = feeds.len();
+        let mut modified = Vec::new();
+        let mut health = Vec::new();
+        for (index, feed) in feeds.into_iter().enumerate() {
+            if refreshed.channels.contains(&index) {
+                modified.push(feed);
+            } else if refreshed.health.contains(&index) {
+                health.push(feed);
             }
         }
-        Ok(acc)
+        let updated = conn
+            .call(move |conn| {
+                let modified: Vec<&PubmedFeed> = modified.iter().collect();
+                let health: Vec<&PubmedFeed> = health.iter().collect();
+                let updated = save_downloads(conn, &modified)?;
+                save_health(conn, &health)?;
+                archive_feeds(conn, &modified)?;
+                Ok(updated)
+            })
+            .await?;
+        log::info!("Updated {} of {} channels.", updated, total);
+        Ok(updated)
+    }
+
+    const FEED_COLUMNS: &str = "id, name, link, channel, subscribers, query, cursor, etag, last_modified,
+        last_success, failures, last_error, last_failure, item_count, last_new_item, refresh_interval, owner, visibility,
+        malformed_items";
+
+    fn feed_from_row(row: &rusqlite::Row) -> Result<PubmedFeed, rusqlite::Error> {
+        Ok(PubmedFeed {
+            name: row.get(1)?,
+            uid: Some(row.get(0)?),
+            link: row.get(2)?,
+            channel: row.get(3)?,
+            subscribers: row.get(4).unwrap_or(0),
+            query: row.get(5)?,
+            cursor: row.get(6)?,
+            etag: row.get(7)?,
+            last_modified: row.get(8)?,
+            health: FeedHealth {
+                last_success: row.get(9)?,
+                failures: row.get::<_, Option<u32>>(10)?.unwrap_or(0),
+                last_error: row.get(11)?,
+                last_failure: row.get(12)?,
+                item_count: row.get(13)?,
+                last_new_item: row.get(14)?,
+                malformed_items: row.get::<_, Option<u32>>(18)?.unwrap_or(0),
+            },
+            refresh_interval: row.get(15)?,
+            owner: row.get(16)?,
+            visibility: row
+                .get::<_, Option<String>>(17)?
+                .map(|visibility| Visibility::from_str(&visibility))
+                .transpose()
+                .map_err(|err| {
+                    rusqlite::Error::FromSqlConversionFailure(17, rusqlite::types::Type::Text, err.into())
+                })?
+                .unwrap_or_default(),
+        })
     }
 
     pub fn get_feed(conn: &Connection, id: u32) -> Result<Option<PubmedFeed>, rusqlite::Error> {
-        let mut stmt = conn
-            .prepare("SELECT id, name, link, channel, last_pushed_guid, subscribers FROM feeds WHERE id=(?1)")?;
+        let mut stmt = conn.prepare(&format!("SELECT {FEED_COLUMNS} FROM feeds WHERE id=(?1)"))?;
         let mut rows = stmt.query([id])?;
-        let row_opt = rows.next()?;
-        if let Some(row) = row_opt {
-            Ok(Some(PubmedFeed {
-                name: row.get(1)?,
-                uid: Some(row.get(0)?),
-                link: row.get(2)?,
-                channel: row.get(3)?,
-                last_pushed_guid: row.get(4)?,
-                subscribers: row.get(5).unwrap_or(0),
-            }))
-        } else {
-            Ok(None)
+        match rows.next()? {
+            Some(row) => Ok(Some(feed_from_row(row)?)),
+            None => Ok(None),
         }
     }
 
     pub fn get_feeds(conn: &Connection) -> Result<Vec<PubmedFeed>, rusqlite::Error> {
-        let mut stmt = conn
-            .prepare("SELECT id, name, link, channel, last_pushed_guid, subscribers FROM feeds")?;
-        let feed_iter = stmt.query_map([], |row| {
-            Ok(PubmedFeed {
-                name: row.get(1)?,
-                uid: Some(row.get(0)?),
-                link: row.get(2)?,
-                channel: {
-                    let s: String = row.get(3)?;
-                    ChannelWrapper::from_json(&s)
-                        .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?
-                },
-                last_pushed_guid: row.get(4)?,
-                subscribers: row.get(5).unwrap_or(0),
-            })
-        })?;
+        let mut stmt = conn.prepare(&format!("SELECT {FEED_COLUMNS} FROM feeds"))?;
+        let feed_iter = stmt.query_map([], feed_from_row)?;
 
         let res: Result<Vec<PubmedFeed>, rusqlite::Error> = feed_iter.into_iter().collect();
         res
@@ -432,6 +1067,7 @@ pub mod sqlite {
         s.push_str(&format!("Feeds: {{ {} }}\n", feedstring));
         s.push_str(&format!("Whitelist: {:?}\n", collection.whitelist));
         s.push_str(&format!("Blacklist: {:?}\n", collection.blacklist));
+        s.push_str(&format!("Style: {:?}\n", collection.style));
         Ok(s)
     }
 
@@ -451,6 +1087,53 @@ pub mod sqlite {
         Ok(deleted)
     }
 
+    /// Deletes the feed with its seen items, and saves the users that no longer
+    /// have it in their collections, in one transaction. Its archived articles
+    /// stay in the archive, but are only found by /search through the other
+    /// feeds they appeared in.
+    pub fn delete_feed(conn: &Connection, id: u32, users: &[User]) -> Result<usize, rusqlite::Error> {
+        log::debug!("Deleting feed {}.", id);
+        let tx = conn.unchecked_transaction()?;
+        for user in users {
+            update_user(&tx, user)?;
+        }
+        let deleted = delete_feed_rows(&tx, id)?;
+        update_subscribers(&tx)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Moves the seen items and archived articles of a feed to another feed,
+    /// so the articles it sent are not sent again by the other one, and
+    /// deletes it. The users, who have the other feed in their collections
+    /// instead, are saved in the same transaction.
+    pub fn merge_feed(conn: &Connection, from: u32, into: u32, users: &[User]) -> Result<(), rusqlite::Error> {
+        let tx = conn.unchecked_transaction()?;
+        for user in users {
+            update_user(&tx, user)?;
+        }
+        tx.execute(
+            "INSERT INTO seen_items (feed_id, item_id, last_seen)
+             SELECT ?2, item_id, last_seen FROM seen_items WHERE feed_id = ?1
+             ON CONFLICT (feed_id, item_id) DO UPDATE SET last_seen = max(last_seen, excluded.last_seen)",
+            [from, into],
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO article_feeds (article_id, feed_id)
+             SELECT article_id, ?2 FROM article_feeds WHERE feed_id = ?1",
+            [from, into],
+        )?;
+        delete_feed_rows(&tx, from)?;
+        update_subscribers(&tx)?;
+        tx.commit()
+    }
+
+    fn delete_feed_rows(conn: &Connection, id: u32) -> Result<usize, rusqlite::Error> {
+        conn.execute("DELETE FROM seen_items WHERE feed_id = ?1", [id])?;
+        conn.execute("DELETE FROM article_feeds WHERE feed_id = ?1", [id])?;
+        conn.execute("DELETE FROM feeds WHERE id = ?1", [id])
+    }
+
     pub fn delete_user(conn: &Connection, id: i64) -> Result<usize, rusqlite::Error> {
         log::debug!("Deleting user {}.", id);
         conn.execute(
@@ -462,11 +1145,14 @@ pub mod sqlite {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
 
     use simple_expand_tilde::expand_tilde;
     use teloxide::types::ParseMode;
 
-    use crate::{datastructs::ItemMetadata, formatter::PreppedMessage};
+    use crate::archive::Article;
+    use crate::datastructs::{ItemMetadata, Visibility};
+    use crate::formatter::PreppedMessage;
 
     use super::*;
 
@@ -484,6 +1170,194 @@ mod tests {
         };
         assert_eq!(version, DB_VERSION)
     }
+
+    #[test]
+    fn test_db_migration() {
+        // The schema of db version 2
+        let path = "target/debug/migration_v2.db3";
+        let _ = std::fs::remove_file(path);
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE users (
+                id           INTEGER PRIMARY KEY,
+                full_name    TEXT,
+                last_pushed  TEXT NOT NULL,
+                collections  TEXT NOT NULL
+            );
+            CREATE TABLE feeds (
+                id            INTEGER PRIMARY KEY AUTOINCREMENT,
+                name          TEXT NOT NULL,
+                link          TEXT NOT NULL UNIQUE,
+                channel       TEXT NOT NULL,
+                last_pushed_guid   INTEGER,
+                subscribers   INTEGER
+            );
+            PRAGMA user_version = 2;",
+        )
+        .unwrap();
+        let channel = std::fs::read_to_string("test/channel_radiology.json").unwrap();
+        conn.execute(
+            "INSERT INTO feeds (id, name, link, channel, subscribers) VALUES (401260, 'Radiology', 'https://pubmed.ncbi.nlm.nih.gov/rss/journals/0401260/', ?1, 1)",
+            [&channel],
+        )
+        .unwrap();
+
+        sqlite::update_db(&conn).unwrap();
+        let version: u32 = conn.query_row("SELECT user_version FROM pragma_user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, DB_VERSION);
+        let feed = sqlite::get_feed(&conn, 401260).unwrap().unwrap();
+        assert!(!feed.channel.items().is_empty());
+        assert_eq!(sqlite::get_seen_items(&conn, 401260).unwrap().len(), feed.channel.items().len());
+        let articles: usize = conn.query_row("SELECT count(*) FROM article_feeds WHERE feed_id = 401260", [], |row| row.get(0)).unwrap();
+        assert_eq!(articles, feed.channel.items().len());
+        // The feeds from before the owner and visibility columns stay public
+        assert_eq!((feed.owner, feed.visibility), (None, Visibility::Public));
+        assert!(sqlite::get_feeds(&conn).unwrap().iter().any(|feed| feed.uid == Some(401260)));
+        // And have no malformed items until their next download
+        assert_eq!(feed.health.malformed_items, 0);
+        assert!(feed.health.problems(chrono::Local::now()).is_empty());
+    }
+
+    #[test]
+    fn test_archive() {
+        let conn = sqlite::new_in_mem().unwrap();
+        let article = Article {
+            key: "pmid:1".to_string(),
+            pmid: Some(1),
+            title: "An article".to_string(),
+            authors: vec!["A Author".to_string()],
+            ..Default::default()
+        };
+        let id = sqlite::archive_article(&conn, 1, &article, 100).unwrap();
+        // The same article in another feed, only with a doi
+        let same = Article {
+            key: "pmid:1".to_string(),
+            pmid: Some(1),
+            doi: Some("10.1000/1".to_string()),
+            title: "An article".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(sqlite::archive_article(&conn, 2, &same, 200).unwrap(), id);
+        let doi_only = Article {
+            key: "doi:10.1000/1".to_string(),
+            doi: Some("10.1000/1".to_string()),
+            title: "An article".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(sqlite::archive_article(&conn, 3, &doi_only, 300).unwrap(), id);
+
+        let stored = sqlite::get_article(&conn, id).unwrap().unwrap();
+        assert_eq!(stored.pmid, Some(1));
+        assert_eq!(stored.doi.as_deref(), Some("10.1000/1"));
+        assert_eq!(stored.authors, vec!["A Author".to_string()]);
+        assert_eq!(sqlite::get_article_feeds(&conn, id).unwrap(), vec![1, 2, 3]);
+        assert!(sqlite::get_article(&conn, id + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_search_articles() {
+        let conn = sqlite::new_in_mem().unwrap();
+        let feed_id = sqlite::get_feeds(&conn).unwrap()[0].uid.unwrap();
+        let articles = [
+            ("pmid:1", "Adrenal vein sampling in primary aldosteronism", None),
+            ("pmid:2", "Liver MRI", Some("Adrenal incidentalomas were found on MRI.")),
+            ("pmid:3", "Prostate MRI", Some("Biparametric MRI")),
+        ];
+        for (key, title, abstract_text) in articles {
+            let article = Article {
+                key: key.to_string(),
+                title: title.to_string(),
+                abstract_text: abstract_text.map(|text| text.to_string()),
+                ..Default::default()
+            };
+            sqlite::archive_article(&conn, feed_id, &article, 100).unwrap();
+        }
+        let (found, total) = sqlite::search_articles(&conn, 1, "adrenal", 5, 0).unwrap();
+        assert_eq!(total, 2);
+        // The match in the title first
+        assert_eq!(found[0].key, "pmid:1");
+        let (found, total) = sqlite::search_articles(&conn, 1, "mri", 1, 1).unwrap();
+        assert_eq!((found.len(), total), (1, 2));
+        let (found, _) = sqlite::search_articles(&conn, 1, "\"vein sampling\"", 5, 0).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(sqlite::search_articles(&conn, 1, "-\"", 5, 0).unwrap().1, 0);
+
+        // The index follows updates
+        let renamed = Article {
+            key: "pmid:3".to_string(),
+            title: "Prostate biopsy".to_string(),
+            ..Default::default()
+        };
+        sqlite::archive_article(&conn, feed_id, &renamed, 200).unwrap();
+        assert_eq!(sqlite::search_articles(&conn, 1, "mri", 5, 0).unwrap().1, 2);
+        assert_eq!(sqlite::search_articles(&conn, 1, "prostate biopsy", 5, 0).unwrap().1, 1);
+
+        // The articles of private feeds are only found by the owner
+        let mut feed = sqlite::get_feed(&conn, feed_id).unwrap().unwrap();
+        feed.owner = Some(2);
+        feed.visibility = Visibility::Private;
+        sqlite::update_feed(&conn, &feed).unwrap();
+        assert_eq!(sqlite::search_articles(&conn, 1, "mri", 5, 0).unwrap().1, 0);
+        assert_eq!(sqlite::search_articles(&conn, 2, "mri", 5, 0).unwrap().1, 2);
+    }
+
+    #[test]
+    fn test_save_downloads() {
+        let conn = sqlite::new_in_mem().unwrap();
+        let mut feeds = sqlite::get_feeds(&conn).unwrap();
+        sqlite::delete_feed(&conn, feeds[2].uid.unwrap(), &[]).unwrap();
+        // Edited by commands during the download
+        let mut edited = feeds[0].clone();
+        edited.name = "Renamed".to_string();
+        edited.subscribers = 5;
+        sqlite::update_feed(&conn, &edited).unwrap();
+        let mut moved = feeds[1].clone();
+        moved.link = "https://example.org/moved.xml".to_string();
+        sqlite::update_feed(&conn, &moved).unwrap();
+
+        for feed in feeds.iter_mut().take(3) {
+            feed.etag = Some("\"abc\"".to_string());
+            feed.health.failures = 2;
+        }
+        let downloaded: Vec<_> = feeds.iter().take(3).collect();
+        assert_eq!(sqlite::save_downloads(&conn, &downloaded).unwrap(), 1);
+
+        let saved = sqlite::get_feed(&conn, feeds[0].uid.unwrap()).unwrap().unwrap();
+        assert_eq!(saved.name, "Renamed");
+        assert_eq!(saved.subscribers, 5);
+        assert_eq!(saved.etag.as_deref(), Some("\"abc\""));
+        assert_eq!(saved.health.failures, 2);
+        // The download of the old link is dropped
+        let saved = sqlite::get_feed(&conn, feeds[1].uid.unwrap()).unwrap().unwrap();
+        assert_eq!(saved.etag, None);
+        // The deleted feed is not written again
+        assert!(sqlite::get_feed(&conn, feeds[2].uid.unwrap()).unwrap().is_none());
+
+        // Only the health, the channel and etag stay as they were
+        let mut feed = feeds[0].clone();
+        feed.etag = None;
+        feed.health.failures = 0;
+        assert_eq!(sqlite::save_health(&conn, &[&feed]).unwrap(), 1);
+        let saved = sqlite::get_feed(&conn, feeds[0].uid.unwrap()).unwrap().unwrap();
+        assert_eq!(saved.etag.as_deref(), Some("\"abc\""));
+        assert_eq!(saved.health.failures, 0);
+    }
+
+    #[test]
+    fn test_seen_items() {
+        let conn = sqlite::new_in_mem().unwrap();
+        let now = 1_700_000_000;
+        let day = 24 * 60 * 60;
+        sqlite::mark_seen(&conn, 1, &[10, 20], now - 90 * day).unwrap();
+        sqlite::mark_seen(&conn, 2, &[10], now).unwrap();
+        assert_eq!(sqlite::get_seen_items(&conn, 1).unwrap(), HashSet::from([10, 20]));
+
+        // Still in the feed: kept
+        sqlite::mark_seen(&conn, 1, &[20, 30], now).unwrap();
+        assert_eq!(sqlite::prune_seen_items(&conn, now).unwrap(), 1);
+        assert_eq!(sqlite::get_seen_items(&conn, 1).unwrap(), HashSet::from([20, 30]));
+        assert_eq!(sqlite::get_seen_items(&conn, 2).unwrap(), HashSet::from([10]));
+    }
     #[test]
     fn test_read_channel() {
         let target = expand_tilde("target/debug/database.db3").unwrap();
@@ -0,0 +1,523 @@
+use chrono::{Local, NaiveDate, TimeZone};
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use rss::extension::dublincore::DublinCoreExtension;
+use rss::{ChannelBuilder, GuidBuilder, Item, ItemBuilder};
+use serde::Deserialize;
+use std::error::Error;
+use std::sync::OnceLock;
+
+use crate::channelwrapper::ChannelWrapper;
+use crate::http::http_client;
+
+// Feeds based on a PubMed search term, fetched with the NCBI E-utilities
+// (esearch for the PMIDs, efetch for the articles) instead of the RSS feeds.
+// They have no limit on the number of results and include all authors, the
+// MeSH terms and the publication types. The items look like the items of the
+// PubMed RSS feeds, so they are filtered and formatted the same way.
+
+pub const DEFAULT_EUTILS_BASE_URL: &str = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils";
+/// Maximum number of articles fetched, and kept in the channel.
+pub const EUTILS_MAX_ITEMS: usize = 100;
+
+static EUTILS_BASE_URL: OnceLock<String> = OnceLock::new();
+
+/// Sets the E-utilities url, eg. to use a mirror. Can only be set once, at startup.
+pub fn set_eutils_base_url(url: String) -> Result<(), &'static str> {
+    EUTILS_BASE_URL
+        .set(url.trim_end_matches('/').to_string())
+        .map_err(|_| "The E-utilities url was already set!")
+}
+
+/// The url set at startup, or the NCBI url.
+pub fn eutils_base_url() -> &'static str {
+    EUTILS_BASE_URL.get_or_init(|| DEFAULT_EUTILS_BASE_URL.to_string())
+}
+
+#[derive(Deserialize)]
+struct EsearchResponse {
+    esearchresult: EsearchResult,
+}
+
+#[derive(Deserialize)]
+struct EsearchResult {
+    #[serde(default)]
+    idlist: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EutilsArticle {
+    pub pmid: u32,
+    pub title: String,
+    pub journal: Option<String>,
+    pub authors: Vec<String>,
+    /// As "2025-04-15", "2025-04" or "2025".
+    pub date: Option<String>,
+    pub doi: Option<String>,
+    /// The sections of the abstract, with their label (eg. "METHODS").
+    pub abstract_sections: Vec<(Option<String>, String)>,
+    pub mesh_terms: Vec<String>,
+    pub publication_types: Vec<String>,
+}
+
+/// The PMIDs of the newest articles matching the term, entered in PubMed since mindate (YYYY/MM/DD).
+pub async fn esearch(
+    base_url: &str,
+    term: &str,
+    mindate: Option<&str>,
+) -> Result<Vec<u32>, Box<dyn Error + Sync + Send>> {
+    let retmax = EUTILS_MAX_ITEMS.to_string();
+    let mut params = vec![
+        ("db", "pubmed"),
+        ("term", term),
+        ("retmode", "json"),
+        ("sort", "most recent"),
+        ("retmax", retmax.as_str()),
+    ];
+    if let Some(mindate) = mindate {
+        params.extend([
+            ("datetype", "edat"),
+            ("mindate", mindate),
+            ("maxdate", "3000"),
+        ]);
+    }
+    let url = reqwest::Url::parse_with_params(&format!("{base_url}/esearch.fcgi"), &params)?;
+    let response: EsearchResponse = eutils_get(url).await?.json().await?;
+    Ok(response
+        .esearchresult
+        .idlist
+        .iter()
+        .filter_map(|id| id.parse().ok())
+        .collect())
+}
+
+/// The PubMed XML of the articles.
+pub async fn efetch(base_url: &str, pmids: &[u32]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let ids = pmids
+        .iter()
+        .map(|pmid| pmid.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+    let url = reqwest::Url::parse_with_params(
+        &format!("{base_url}/efetch.fcgi"),
+        &[("db", "pubmed"), ("id", ids.as_str()), ("retmode", "xml")],
+    )?;
+    Ok(eutils_get(url).await?.text().await?)
+}
+
+/// Sends the request with the tool and email parameters NCBI asks for.
+async fn eutils_get(url: reqwest::Url) -> Result<reqwest::Response, Box<dyn Error + Sync + Send>> {
+    let client = http_client();
+    let mut request = client.get(url).query(&[("tool", "rssnotify")]);
+    if let Some(email) = &client.settings().email {
+        request = request.query(&[("email", email)]);
+    }
+    Ok(client.send(request).await?.error_for_status()?)
+}
+
+/// Searches the term and returns the new articles merged with the items of the previous channel.
+pub async fn download_channel(
+    base_url: &str,
+    term: &str,
+    cursor: Option<&str>,
+    previous: &ChannelWrapper,
+) -> Result<ChannelWrapper, Box<dyn Error + Sync + Send>> {
+    let pmids = esearch(base_url, term, cursor).await?;
+    log::debug!(
+        "E-utilities search {:?} found {} articles",
+        term,
+        pmids.len()
+    );
+    let articles = match pmids.is_empty() {
+        true => Vec::new(),
+        false => parse_efetch(&efetch(base_url, &pmids).await?)?,
+    };
+    let mut items: Vec<Item> = articles.iter().map(EutilsArticle::to_item).collect();
+    items.extend(previous.items().iter().cloned());
+    // Newest first, as in the PubMed RSS feeds
+    items.sort_by_key(|item| std::cmp::Reverse(ChannelWrapper::parse_guid(item)));
+    items.dedup_by_key(|item| ChannelWrapper::parse_guid(item));
+    items.truncate(EUTILS_MAX_ITEMS);
+    let channel = ChannelBuilder::default()
+        .title(format!("PubMed search: {term}"))
+        .link(search_link(term))
+        .last_build_date(Some(Local::now().to_rfc2822()))
+        .items(items)
+        .build();
+    Ok(ChannelWrapper::build(channel))
+}
+
+/// The PubMed page of the search.
+pub fn search_link(term: &str) -> String {
+    reqwest::Url::parse_with_params("https://pubmed.ncbi.nlm.nih.gov/", &[("term", term)])
+        .map(|url| url.to_string())
+        .unwrap_or_default()
+}
+
+/// The date cursor used to only search the articles added since the last update.
+pub fn cursor_today() -> String {
+    Local::now().format("%Y/%m/%d").to_string()
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .try_get_attribute(name)
+        .ok()
+        .flatten()
+        .map(|attr| String::from_utf8_lossy(&attr.value).to_string())
+}
+
+fn month_number(month: &str) -> Option<u32> {
+    match month.parse::<u32>() {
+        Ok(month) => Some(month),
+        Err(_) => month
+            .parse::<chrono::Month>()
+            .ok()
+            .map(|month| month.number_from_month()),
+    }
+}
+
+/// Parses the PubmedArticleSet returned by efetch.
+pub fn parse_efetch(xml: &str) -> Result<Vec<EutilsArticle>, Box<dyn Error + Sync + Send>> {
+    let mut reader = Reader::from_str(xml);
+    let mut articles = Vec::new();
+    let mut article = EutilsArticle::default();
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let (mut last_name, mut fore_name) = (String::new(), String::new());
+    // (year, month, day) of the electronic publication and of the journal issue
+    let mut article_date: Vec<String> = Vec::new();
+    let mut issue_date: Vec<String> = Vec::new();
+    let mut label: Option<String> = None;
+    let mut id_type: Option<String> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                let name = String::from_utf8_lossy(element.name().as_ref()).to_string();
+                match name.as_str() {
+                    "PubmedArticle" => {
+                        article = EutilsArticle::default();
+                        article_date.clear();
+                        issue_date.clear();
+                    }
+                    "AbstractText" => label = attribute(&element, "Label"),
+                    "ELocationID" => id_type = attribute(&element, "EIdType"),
+                    "ArticleId" => id_type = attribute(&element, "IdType"),
+                    "Author" => (last_name, fore_name) = (String::new(), String::new()),
+                    _ => (),
+                }
+                // Mixed content (eg. <i> in a title) is added to the text of the parent
+                if !path
+                    .last()
+                    .is_some_and(|p| p == "ArticleTitle" || p == "AbstractText")
+                {
+                    text.clear();
+                }
+                path.push(name);
+            }
+            Event::Text(t) => text.push_str(&t.unescape()?),
+            Event::CData(t) => text.push_str(&String::from_utf8_lossy(&t)),
+            Event::End(_) => {
+                let name = path.pop().unwrap_or_default();
+                let parent = path.last().map(|p| p.as_str()).unwrap_or("");
+                let value = text.trim().to_string();
+                match (parent, name.as_str()) {
+                    ("MedlineCitation", "PMID") => article.pmid = value.parse().unwrap_or(0),
+                    (_, "ArticleTitle") => article.title = value,
+                    ("Journal", "Title") => article.journal = Some(value),
+                    (_, "AbstractText") => {
+                        article.abstract_sections.push((label.take(), value));
+                    }
+                    ("Author", "LastName") => last_name = value,
+                    ("Author", "ForeName") => fore_name = value,
+                    ("Author", "CollectiveName") => last_name = value,
+                    ("AuthorList", "Author") => {
+                        let author = format!("{} {}", fore_name, last_name);
+                        article.authors.push(author.trim().to_string());
+                    }
+                    ("PublicationTypeList", "PublicationType") => {
+                        article.publication_types.push(value)
+                    }
+                    ("MeshHeading", "DescriptorName") => article.mesh_terms.push(value),
+                    ("Article", "ELocationID") | ("ArticleIdList", "ArticleId")
+                        if id_type.as_deref() == Some("doi") && article.doi.is_none() =>
+                    {
+                        article.doi = Some(value)
+                    }
+                    ("ArticleDate", "Year" | "Month" | "Day") => article_date.push(value),
+                    ("PubDate", "Year" | "Month" | "Day") => issue_date.push(value),
+                    (_, "PubmedArticle") => {
+                        let date = match article_date.is_empty() {
+                            true => &issue_date,
+                            false => &article_date,
+                        };
+                        article.date = format_date(date);
+                        articles.push(std::mem::take(&mut article));
+                    }
+                    _ => (),
+                }
+                if !path
+                    .last()
+                    .is_some_and(|p| p == "ArticleTitle" || p == "AbstractText")
+                {
+                    text.clear();
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(articles)
+}
+
+/// Formats [year, month, day] as "2025-04-15". The month can be a number or a name like "Apr".
+fn format_date(parts: &[String]) -> Option<String> {
+    let year: i32 = parts.first()?.parse().ok()?;
+    match parts.get(1).and_then(|month| month_number(month)) {
+        Some(month) => match parts.get(2).and_then(|day| day.parse::<u32>().ok()) {
+            Some(day) => Some(format!("{:04}-{:02}-{:02}", year, month, day)),
+            None => Some(format!("{:04}-{:02}", year, month)),
+        },
+        None => Some(format!("{:04}", year)),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl EutilsArticle {
+    /// An item like the items of the PubMed RSS feeds, with the MeSH terms as
+    /// Dublin Core subjects and the publication types as Dublin Core types.
+    pub fn to_item(&self) -> Item {
+        let link = format!("https://pubmed.ncbi.nlm.nih.gov/{}/", self.pmid);
+        let mut content = String::from("<div>");
+        if let Some(journal) = &self.journal {
+            content.push_str(&format!("<p><b>{}</b></p>", escape_html(journal)));
+        }
+        if !self.abstract_sections.is_empty() {
+            content.push_str("<p><b>ABSTRACT</b></p>");
+            for (label, text) in self.abstract_sections.iter() {
+                match label {
+                    Some(label) => content.push_str(&format!(
+                        "<p><b>{}:</b> {}</p>",
+                        escape_html(&label.to_uppercase()),
+                        escape_html(text)
+                    )),
+                    None => content.push_str(&format!("<p>{}</p>", escape_html(text))),
+                }
+            }
+        }
+        content.push_str(&format!(
+            "<p>PMID:<a href=\"{}\">{}</a></p></div>",
+            link, self.pmid
+        ));
+
+        let mut identifiers = vec![format!("pmid:{}", self.pmid)];
+        if let Some(doi) = &self.doi {
+            identifiers.push(format!("doi:{}", doi));
+        }
+        let dublin_core = DublinCoreExtension {
+            creators: self.authors.clone(),
+            dates: self.date.iter().cloned().collect(),
+            identifiers,
+            sources: self.journal.iter().cloned().collect(),
+            subjects: self.mesh_terms.clone(),
+            titles: vec![self.title.clone()],
+            types: self.publication_types.clone(),
+            ..Default::default()
+        };
+        let pub_date = self
+            .date
+            .as_deref()
+            .and_then(|date| {
+                let mut parts = date.split('-').map(|part| part.parse::<u32>().ok());
+                let year = parts.next().flatten()?;
+                let month = parts.next().flatten().unwrap_or(1);
+                let day = parts.next().flatten().unwrap_or(1);
+                NaiveDate::from_ymd_opt(year as i32, month, day)
+            })
+            .and_then(|date| date.and_hms_opt(6, 0, 0))
+            .and_then(|date| Local.from_local_datetime(&date).single())
+            .map(|date| date.to_rfc2822());
+        let description = self
+            .abstract_sections
+            .iter()
+            .map(|(_, text)| text.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        ItemBuilder::default()
+            .title(Some(self.title.clone()))
+            .link(Some(link))
+            .description(Some(description))
+            .guid(Some(
+                GuidBuilder::default()
+                    .value(format!("pubmed:{}", self.pmid))
+                    .permalink(false)
+                    .build(),
+            ))
+            .pub_date(pub_date)
+            .content(Some(content))
+            .dublin_core_ext(Some(dublin_core))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testserver::{self, Response};
+
+    const EFETCH: &str = r#"<?xml version="1.0" ?>
+<!DOCTYPE PubmedArticleSet PUBLIC "-//NLM//DTD PubMedArticle, 1st January 2025//EN" "https://dtd.nlm.nih.gov/ncbi/pubmed/out/pubmed_250101.dtd">
+<PubmedArticleSet>
+<PubmedArticle>
+  <MedlineCitation Status="MEDLINE" Owner="NLM">
+    <PMID Version="1">40232138</PMID>
+    <Article PubModel="Print">
+      <Journal>
+        <JournalIssue CitedMedium="Internet">
+          <Volume>315</Volume><Issue>1</Issue>
+          <PubDate><Year>2025</Year><Month>Apr</Month></PubDate>
+        </JournalIssue>
+        <Title>Radiology</Title>
+      </Journal>
+      <ArticleTitle>Intraprotocol <i>Adrenal</i> Vein Sampling &amp; Aldosteronism</ArticleTitle>
+      <ELocationID EIdType="doi" ValidYN="Y">10.1148/radiol.240631</ELocationID>
+      <Abstract>
+        <AbstractText Label="Background" NlmCategory="BACKGROUND">Adrenal vein sampling is the reference.</AbstractText>
+        <AbstractText Label="Results" NlmCategory="RESULTS">A total of 83 patients (P &lt; .001).</AbstractText>
+      </Abstract>
+      <AuthorList CompleteYN="Y">
+        <Author ValidYN="Y"><LastName>Mermejo</LastName><ForeName>Livia M</ForeName></Author>
+        <Author ValidYN="Y"><LastName>Zhao</LastName><ForeName>Lili</ForeName></Author>
+        <Author ValidYN="Y"><CollectiveName>AVS Study Group</CollectiveName></Author>
+      </AuthorList>
+      <PublicationTypeList>
+        <PublicationType UI="D016428">Journal Article</PublicationType>
+        <PublicationType UI="D016448">Multicenter Study</PublicationType>
+      </PublicationTypeList>
+    </Article>
+    <CommentsCorrectionsList>
+      <CommentsCorrections RefType="CommentIn"><PMID Version="1">40232999</PMID></CommentsCorrections>
+    </CommentsCorrectionsList>
+    <MeshHeadingList>
+      <MeshHeading><DescriptorName UI="D006801" MajorTopicYN="N">Humans</DescriptorName></MeshHeading>
+      <MeshHeading><DescriptorName UI="D006929" MajorTopicYN="Y">Hyperaldosteronism</DescriptorName><QualifierName UI="Q000000981" MajorTopicYN="N">diagnostic imaging</QualifierName></MeshHeading>
+    </MeshHeadingList>
+  </MedlineCitation>
+  <PubmedData>
+    <ArticleIdList>
+      <ArticleId IdType="pubmed">40232138</ArticleId>
+      <ArticleId IdType="doi">10.1148/radiol.240631</ArticleId>
+    </ArticleIdList>
+  </PubmedData>
+</PubmedArticle>
+<PubmedArticle>
+  <MedlineCitation Status="PubMed-not-MEDLINE" Owner="NLM">
+    <PMID Version="1">40232100</PMID>
+    <Article PubModel="Electronic">
+      <Journal><Title>Insights into imaging</Title></Journal>
+      <ArticleTitle>An article without abstract</ArticleTitle>
+      <ArticleDate DateType="Electronic"><Year>2025</Year><Month>04</Month><Day>14</Day></ArticleDate>
+    </Article>
+  </MedlineCitation>
+</PubmedArticle>
+</PubmedArticleSet>"#;
+
+    #[test]
+    fn test_parse_efetch() {
+        let articles = parse_efetch(EFETCH).unwrap();
+        assert_eq!(articles.len(), 2);
+        let article = &articles[0];
+        assert_eq!(article.pmid, 40232138);
+        assert_eq!(
+            article.title,
+            "Intraprotocol Adrenal Vein Sampling & Aldosteronism"
+        );
+        assert_eq!(article.journal.as_deref(), Some("Radiology"));
+        assert_eq!(
+            article.authors,
+            vec!["Livia M Mermejo", "Lili Zhao", "AVS Study Group"]
+        );
+        assert_eq!(article.date.as_deref(), Some("2025-04"));
+        assert_eq!(article.doi.as_deref(), Some("10.1148/radiol.240631"));
+        assert_eq!(article.abstract_sections.len(), 2);
+        assert_eq!(
+            article.abstract_sections[1],
+            (
+                Some("Results".to_string()),
+                "A total of 83 patients (P < .001).".to_string()
+            )
+        );
+        assert_eq!(article.mesh_terms, vec!["Humans", "Hyperaldosteronism"]);
+        assert_eq!(
+            article.publication_types,
+            vec!["Journal Article", "Multicenter Study"]
+        );
+        assert_eq!(articles[1].date.as_deref(), Some("2025-04-14"));
+        assert!(articles[1].abstract_sections.is_empty());
+
+        // The items are formatted like the items of the PubMed RSS feeds
+        let item = article.to_item();
+        assert_eq!(ChannelWrapper::parse_guid(&item), Some(40232138));
+        let item_metadata = crate::datastructs::ItemMetadata::default();
+        let message = crate::formatter::PreppedMessage::build(&item, &item_metadata);
+        assert_eq!(message.pmid.as_deref(), Some("40232138"));
+        assert_eq!(message.doi.as_deref(), Some("10.1148/radiol.240631"));
+        assert!(
+            message
+                .plain_abstract()
+                .unwrap()
+                .contains("A total of 83 patients (P < .001).")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download_channel() {
+        let base_url = testserver::serve(2, |request| {
+            assert!(request.contains("tool=rssnotify"));
+            if request.contains("/esearch.fcgi") {
+                assert!(request.contains("mindate=2025%2F04%2F14"));
+                Response::ok(
+                    r#"{"esearchresult": {"count": "2", "idlist": ["40232100", "40232138"]}}"#,
+                )
+            } else {
+                assert!(request.contains("id=40232100%2C40232138"));
+                Response::ok(EFETCH)
+            }
+        });
+        let previous_item = ItemBuilder::default()
+            .title(Some("Older article".to_string()))
+            .guid(Some(
+                GuidBuilder::default()
+                    .value("pubmed:40232000".to_string())
+                    .build(),
+            ))
+            .build();
+        let previous =
+            ChannelWrapper::build(ChannelBuilder::default().items(vec![previous_item]).build());
+        let channel = download_channel(
+            &base_url,
+            "adrenal vein sampling",
+            Some("2025/04/14"),
+            &previous,
+        )
+        .await
+        .unwrap();
+        let pmids: Vec<Option<u32>> = channel
+            .items()
+            .iter()
+            .map(ChannelWrapper::parse_guid)
+            .collect();
+        assert_eq!(pmids, vec![Some(40232138), Some(40232100), Some(40232000)]);
+        assert_eq!(
+            channel.link(),
+            "https://pubmed.ncbi.nlm.nih.gov/?term=adrenal+vein+sampling"
+        );
+    }
+}
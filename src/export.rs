@@ -0,0 +1,329 @@
+use serde::Serialize;
+use strum_macros::EnumString;
+
+use crate::formatter::PreppedMessage;
+
+/// Citation formats that reference managers like Zotero and EndNote can import.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString)]
+pub enum ExportFormat {
+    #[strum(ascii_case_insensitive)]
+    Ris,
+    #[strum(ascii_case_insensitive, serialize = "bibtex", serialize = "bib")]
+    BibTex,
+    #[strum(
+        ascii_case_insensitive,
+        serialize = "csljson",
+        serialize = "csl-json",
+        serialize = "csl"
+    )]
+    CslJson,
+}
+
+impl ExportFormat {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Ris => "ris",
+            ExportFormat::BibTex => "bib",
+            ExportFormat::CslJson => "json",
+        }
+    }
+}
+
+/// Exports the articles to a file in the given citation format.
+pub fn export(messages: &[PreppedMessage], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Ris => messages.iter().map(to_ris).collect(),
+        ExportFormat::BibTex => to_bibtex(messages),
+        ExportFormat::CslJson => to_csl_json(messages),
+    }
+}
+
+/// Splits "Livia M Mermejo" in the family name "Mermejo" and given names "Livia M".
+fn split_name(author: &str) -> (&str, &str) {
+    match author.trim().rsplit_once(' ') {
+        Some((given, family)) => (family, given),
+        None => (author.trim(), ""),
+    }
+}
+
+/// The year, month and day of a date like "2025-04-15".
+fn date_parts(date: &str) -> Vec<u32> {
+    date.split(['-', '/'])
+        .take(3)
+        .map_while(|part| part.trim().parse().ok())
+        .collect()
+}
+
+fn to_ris(message: &PreppedMessage) -> String {
+    let mut ris = vec!["TY  - JOUR".to_string()];
+    ris.push(format!("TI  - {}", message.plain_title()));
+    for author in message.authors.iter() {
+        let (family, given) = split_name(author);
+        ris.push(format!("AU  - {}, {}", family, given));
+    }
+    if let Some(journal) = &message.journal {
+        ris.push(format!("JO  - {}", journal));
+    }
+    if let Some(date) = &message.date {
+        let parts = date_parts(date);
+        if let Some(year) = parts.first() {
+            ris.push(format!("PY  - {}", year));
+        }
+        if parts.len() == 3 {
+            ris.push(format!(
+                "DA  - {}/{:02}/{:02}",
+                parts[0], parts[1], parts[2]
+            ));
+        }
+    }
+    if let Some(doi) = &message.doi {
+        ris.push(format!("DO  - {}", doi));
+        ris.push(format!("UR  - https://doi.org/{}", doi));
+    } else if let Some(link) = &message.link {
+        ris.push(format!("UR  - {}", link));
+    }
+    if let Some(pmid) = &message.pmid {
+        ris.push(format!("AN  - {}", pmid));
+    }
+    if let Some(abstr) = message.plain_abstract() {
+        ris.push(format!("AB  - {}", abstr.replace('\n', " ")));
+    }
+    ris.push("ER  - ".to_string());
+    ris.join("\r\n") + "\r\n"
+}
+
+/// 1 is a, 26 is z, 27 is aa, 28 is ab...
+fn letter_suffix(mut n: usize) -> String {
+    let mut letters = Vec::new();
+    while n > 0 {
+        n -= 1;
+        letters.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+fn escape_bibtex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '&' | '%' | '$' | '#' | '_' | '{' | '}') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn to_bibtex(messages: &[PreppedMessage]) -> String {
+    let mut keys: Vec<String> = Vec::new();
+    let mut entries = Vec::new();
+    for message in messages {
+        let year = message
+            .date
+            .as_deref()
+            .and_then(|date| date_parts(date).first().copied());
+        // Eg. Mermejo2025, Mermejo2025b for the second article
+        let family = message
+            .authors
+            .first()
+            .map(|author| split_name(author).0)
+            .unwrap_or("article");
+        let base: String = format!(
+            "{}{}",
+            family,
+            year.map(|y| y.to_string()).unwrap_or_default()
+        )
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+        let mut key = base.clone();
+        let mut suffix = 2;
+        while keys.contains(&key) {
+            key = format!("{}{}", base, letter_suffix(suffix));
+            suffix += 1;
+        }
+        keys.push(key.clone());
+
+        let mut fields = vec![format!(
+            "  title = {{{{{}}}}}",
+            escape_bibtex(&message.plain_title())
+        )];
+        if !message.authors.is_empty() {
+            let authors: Vec<String> = message
+                .authors
+                .iter()
+                .map(|author| {
+                    let (family, given) = split_name(author);
+                    format!("{}, {}", family, given)
+                })
+                .collect();
+            fields.push(format!(
+                "  author = {{{}}}",
+                escape_bibtex(&authors.join(" and "))
+            ));
+        }
+        if let Some(journal) = &message.journal {
+            fields.push(format!("  journal = {{{}}}", escape_bibtex(journal)));
+        }
+        if let Some(year) = year {
+            fields.push(format!("  year = {{{}}}", year));
+        }
+        if let Some(doi) = &message.doi {
+            fields.push(format!("  doi = {{{}}}", doi));
+        }
+        if let Some(pmid) = &message.pmid {
+            fields.push(format!("  pmid = {{{}}}", pmid));
+        }
+        if let Some(abstr) = message.plain_abstract() {
+            fields.push(format!(
+                "  abstract = {{{}}}",
+                escape_bibtex(&abstr.replace('\n', " "))
+            ));
+        }
+        entries.push(format!("@article{{{},\n{}\n}}\n", key, fields.join(",\n")));
+    }
+    entries.join("\n")
+}
+
+#[derive(Serialize)]
+struct CslName<'a> {
+    family: &'a str,
+    given: &'a str,
+}
+
+#[derive(Serialize)]
+struct CslDate {
+    #[serde(rename = "date-parts")]
+    date_parts: Vec<Vec<u32>>,
+}
+
+#[derive(Serialize)]
+struct CslItem<'a> {
+    id: String,
+    #[serde(rename = "type")]
+    item_type: &'static str,
+    title: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    author: Vec<CslName<'a>>,
+    #[serde(rename = "container-title", skip_serializing_if = "Option::is_none")]
+    container_title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issued: Option<CslDate>,
+    #[serde(rename = "DOI", skip_serializing_if = "Option::is_none")]
+    doi: Option<&'a str>,
+    #[serde(rename = "PMID", skip_serializing_if = "Option::is_none")]
+    pmid: Option<&'a str>,
+    #[serde(rename = "URL", skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(rename = "abstract", skip_serializing_if = "Option::is_none")]
+    abstr: Option<String>,
+}
+
+fn to_csl_json(messages: &[PreppedMessage]) -> String {
+    let items: Vec<CslItem> = messages
+        .iter()
+        .enumerate()
+        .map(|(index, message)| CslItem {
+            id: message
+                .pmid
+                .as_ref()
+                .map(|pmid| format!("pmid:{}", pmid))
+                .unwrap_or_else(|| format!("item{}", index + 1)),
+            item_type: "article-journal",
+            title: message.plain_title(),
+            author: message
+                .authors
+                .iter()
+                .map(|author| {
+                    let (family, given) = split_name(author);
+                    CslName { family, given }
+                })
+                .collect(),
+            container_title: message.journal.as_deref(),
+            issued: message
+                .date
+                .as_deref()
+                .map(date_parts)
+                .filter(|parts| !parts.is_empty())
+                .map(|parts| CslDate {
+                    date_parts: vec![parts],
+                }),
+            doi: message.doi.as_deref(),
+            pmid: message.pmid.as_deref(),
+            url: message
+                .doi
+                .as_ref()
+                .map(|doi| format!("https://doi.org/{}", doi))
+                .or_else(|| message.link.clone()),
+            abstr: message.plain_abstract(),
+        })
+        .collect();
+    // Serializing these structs can not fail.
+    serde_json::to_string_pretty(&items).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channelwrapper::ChannelWrapper;
+    use crate::datastructs::ItemMetadata;
+    use std::str::FromStr;
+    use std::{fs::File, io::Read};
+
+    #[test]
+    fn test_export() {
+        let mut file = File::open("test/channel_radiology.json").unwrap();
+        let mut json = String::new();
+        file.read_to_string(&mut json).unwrap();
+        let channel = ChannelWrapper::from_json(&json).unwrap();
+        let item_metadata = ItemMetadata::default();
+        let messages: Vec<PreppedMessage> = channel.items[6..8]
+            .iter()
+            .map(|item| PreppedMessage::build(item, &item_metadata))
+            .collect();
+
+        let ris = export(&messages, ExportFormat::from_str("RIS").unwrap());
+        assert_eq!(ris.matches("TY  - JOUR\r\n").count(), 2);
+        assert!(ris.contains("TI  - Intraprotocol Adrenal Vein Sampling Inconsistencies in Primary Aldosteronism Lateralization\r\n"));
+        assert!(ris.contains("AU  - Mermejo, Livia M\r\nAU  - Zhao, Lili\r\n"));
+        assert!(ris.contains("PY  - 2025\r\nDA  - 2025/04/15\r\n"));
+        assert!(ris.contains("DO  - 10.1148/radiol.240631\r\n"));
+        assert!(ris.contains("AN  - 40232138\r\n"));
+
+        let bibtex = export(&messages, ExportFormat::from_str("bib").unwrap());
+        assert!(bibtex.contains("@article{Mermejo2025,\n  title = {{Intraprotocol Adrenal"));
+        assert!(bibtex.contains("  author = {Mermejo, Livia M and Zhao, Lili and"));
+        assert!(bibtex.contains("  doi = {10.1148/radiol.240631},\n  pmid = {40232138},"));
+
+        let csl = export(&messages, ExportFormat::from_str("csl-json").unwrap());
+        let parsed: serde_json::Value = serde_json::from_str(&csl).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 2);
+        assert_eq!(parsed[1]["id"], "pmid:40232138");
+        assert_eq!(parsed[1]["container-title"], "Radiology");
+        assert_eq!(parsed[1]["author"][0]["family"], "Mermejo");
+        assert_eq!(parsed[1]["issued"]["date-parts"][0][1], 4);
+    }
+
+    #[test]
+    fn test_bibtex_keys() {
+        let mut file = File::open("test/channel_radiology.json").unwrap();
+        let mut json = String::new();
+        file.read_to_string(&mut json).unwrap();
+        let channel = ChannelWrapper::from_json(&json).unwrap();
+        let item_metadata = ItemMetadata::default();
+        // The same article 30 times: the keys go on after z
+        let messages: Vec<PreppedMessage> = std::iter::repeat_n(&channel.items[7], 30)
+            .map(|item| PreppedMessage::build(item, &item_metadata))
+            .collect();
+        let bibtex = export(&messages, ExportFormat::BibTex);
+        assert!(bibtex.contains("@article{Mermejo2025,"));
+        assert!(bibtex.contains("@article{Mermejo2025b,"));
+        assert!(bibtex.contains("@article{Mermejo2025z,"));
+        assert!(bibtex.contains("@article{Mermejo2025aa,"));
+        assert!(bibtex.contains("@article{Mermejo2025ad,"));
+        assert_eq!(letter_suffix(1), "a");
+        assert_eq!(letter_suffix(26), "z");
+        assert_eq!(letter_suffix(27), "aa");
+        assert_eq!(letter_suffix(703), "aaa");
+    }
+}
@@ -0,0 +1,267 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+// Journal specific fixes for the abstracts. They are declared in a toml file, eg:
+//
+// [default]
+// strip_suffix = [" ©RSNA"]
+// headings = ["Background", "Purpose", "Results", "Conclusion"]
+// max_authors = 3
+//
+// [[journal]]
+// journal = "AJR. American journal of roentgenology"
+// nlm_id = 7708173
+// replace = [{ regex = '\*\*([A-Za-z ]+?)[:.]\*\*(\w)', replacement = " $1: $2" }]
+//
+// A journal rule is matched on the feed id (= NLM id for journal feeds) or on the journal
+// name of the item. Every field it leaves out is taken from the default rule.
+
+static FORMAT_RULES: OnceLock<FormatRules> = OnceLock::new();
+
+const DEFAULT_STRIP_SUFFIX: &[&str] = &[" ©RSNA"];
+
+/// Only the first author is shown, followed by "et al.".
+const DEFAULT_MAX_AUTHORS: usize = 1;
+
+const DEFAULT_HEADINGS: &[&str] = &[
+    "Background",
+    "Objective",
+    "Purpose",
+    "Materials and Methods",
+    "Results",
+    "Conclusion",
+    "Clinical Impact",
+    "Evidence Synthesis",
+    "Evidence Acquisition",
+];
+
+// For AJR: **Background:**Text
+const DEFAULT_REPLACE: &[(&str, &str)] =
+    &[(r"(?m)(^|\w|\.)\*\*([A-Za-z ]+?)[:.]\*\*(\w)", "$1 $2: $3")];
+
+#[derive(Deserialize, Debug, Default)]
+struct RawFormatRules {
+    default: Option<RawFormatRule>,
+    #[serde(default)]
+    journal: Vec<RawFormatRule>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+struct RawFormatRule {
+    journal: Option<String>,
+    nlm_id: Option<u32>,
+    strip_suffix: Option<Vec<String>>,
+    replace: Option<Vec<RawReplacement>>,
+    headings: Option<Vec<String>>,
+    max_authors: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RawReplacement {
+    regex: String,
+    replacement: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct FormatRule {
+    pub journal: Option<String>,
+    pub nlm_id: Option<u32>,
+    /// The abstract is cut off at the first occurrence of any of these.
+    pub strip_suffix: Vec<String>,
+    /// Applied in order to the abstract, before it is formatted.
+    pub replace: Vec<(Regex, String)>,
+    pub headings: Vec<String>,
+    /// Matches the headings of a structured abstract, eg. "Background" or "Results".
+    pub headings_re: Option<Regex>,
+    /// Number of authors shown before "et al.", 0 hides the authors.
+    pub max_authors: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FormatRules {
+    pub default: FormatRule,
+    pub journals: Vec<FormatRule>,
+}
+
+impl Default for FormatRule {
+    fn default() -> Self {
+        let raw = RawFormatRule {
+            strip_suffix: Some(DEFAULT_STRIP_SUFFIX.iter().map(|s| s.to_string()).collect()),
+            replace: Some(
+                DEFAULT_REPLACE
+                    .iter()
+                    .map(|(regex, replacement)| RawReplacement {
+                        regex: regex.to_string(),
+                        replacement: replacement.to_string(),
+                    })
+                    .collect(),
+            ),
+            headings: Some(DEFAULT_HEADINGS.iter().map(|s| s.to_string()).collect()),
+            max_authors: Some(DEFAULT_MAX_AUTHORS),
+            ..Default::default()
+        };
+        // The built in rules are valid regexes.
+        FormatRule::build(raw, None).unwrap()
+    }
+}
+
+impl FormatRule {
+    fn build(raw: RawFormatRule, parent: Option<&FormatRule>) -> Result<FormatRule, regex::Error> {
+        let replace = match raw.replace {
+            Some(replace) => replace
+                .into_iter()
+                .map(|r| Ok((Regex::new(&r.regex)?, r.replacement)))
+                .collect::<Result<Vec<(Regex, String)>, regex::Error>>()?,
+            None => parent.map(|p| p.replace.clone()).unwrap_or_default(),
+        };
+        let headings = match raw.headings {
+            Some(headings) => headings,
+            None => parent.map(|p| p.headings.clone()).unwrap_or_default(),
+        };
+        Ok(FormatRule {
+            journal: raw.journal,
+            nlm_id: raw.nlm_id,
+            strip_suffix: raw
+                .strip_suffix
+                .or_else(|| parent.map(|p| p.strip_suffix.clone()))
+                .unwrap_or_default(),
+            replace,
+            headings_re: Self::build_headings_re(&headings)?,
+            headings,
+            max_authors: raw
+                .max_authors
+                .or_else(|| parent.map(|p| p.max_authors))
+                .unwrap_or(DEFAULT_MAX_AUTHORS),
+        })
+    }
+
+    fn build_headings_re(headings: &[String]) -> Result<Option<Regex>, regex::Error> {
+        if headings.is_empty() {
+            return Ok(None);
+        }
+        let alternatives = headings
+            .iter()
+            .map(|h| regex::escape(h))
+            .collect::<Vec<String>>()
+            .join("|");
+        Regex::new(&format!(r"(\.|^) ?({})[:.]? ?([A-Z])", alternatives)).map(Some)
+    }
+
+    /// Applies the strip_suffix and replace rules to the unformatted abstract.
+    pub fn apply(&self, content: &str) -> String {
+        let mut content = content.to_string();
+        for suffix in self.strip_suffix.iter() {
+            if let Some(start) = content.find(suffix.as_str()) {
+                content.truncate(start)
+            }
+        }
+        for (re, replacement) in self.replace.iter() {
+            content = re.replace_all(&content, replacement.as_str()).into_owned();
+        }
+        content
+    }
+}
+
+impl FormatRules {
+    pub fn from_toml(toml: &str) -> Result<FormatRules, Box<dyn Error>> {
+        let raw: RawFormatRules = toml::from_str(toml)?;
+        let default = match raw.default {
+            Some(default) => FormatRule::build(default, Some(&FormatRule::default()))?,
+            None => FormatRule::default(),
+        };
+        let journals = raw
+            .journal
+            .into_iter()
+            .map(|rule| {
+                if rule.journal.is_none() && rule.nlm_id.is_none() {
+                    return Err("Every [[journal]] rule needs a journal name or nlm_id!".into());
+                }
+                FormatRule::build(rule, Some(&default)).map_err(|e| e.into())
+            })
+            .collect::<Result<Vec<FormatRule>, Box<dyn Error>>>()?;
+        Ok(FormatRules { default, journals })
+    }
+
+    pub fn from_file(path: &Path) -> Result<FormatRules, Box<dyn Error>> {
+        FormatRules::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Returns the rule of the journal, or the default rule for unknown journals.
+    pub fn get(&self, feed_id: Option<u32>, journal: Option<&str>) -> &FormatRule {
+        self.journals
+            .iter()
+            .find(|rule| rule.nlm_id.is_some() && rule.nlm_id == feed_id)
+            .or_else(|| {
+                let journal = journal?;
+                self.journals.iter().find(|rule| {
+                    rule.journal
+                        .as_ref()
+                        .is_some_and(|j| j.eq_ignore_ascii_case(journal))
+                })
+            })
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Sets the rules used by the formatter. Can only be set once, at startup.
+pub fn set_format_rules(rules: FormatRules) -> Result<(), &'static str> {
+    FORMAT_RULES
+        .set(rules)
+        .map_err(|_| "The formatting rules were already set!")
+}
+
+/// The rules set at startup, or the built in defaults.
+pub fn format_rules() -> &'static FormatRules {
+    FORMAT_RULES.get_or_init(FormatRules::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_from_file() {
+        let rules = FormatRules::from_file(Path::new("test/format_rules.toml")).unwrap();
+        assert_eq!(rules.journals.len(), 2);
+
+        // Unknown journals fall back to the default
+        let rule = rules.get(Some(1234), Some("Unknown journal"));
+        assert_eq!(rule.strip_suffix, vec![" ©RSNA".to_string()]);
+        assert!(rule.headings.contains(&"Clinical Impact".to_string()));
+
+        // Matched on nlm id
+        let rule = rules.get(Some(7708173), None);
+        assert_eq!(
+            rule.journal.as_deref(),
+            Some("AJR. American journal of roentgenology")
+        );
+        assert_eq!(
+            rule.apply("**Background:**Text.**Conclusion:**Done."),
+            " Background: Text. Conclusion: Done."
+        );
+
+        // Matched on journal name, inherits the replace rules from the default
+        let rule = rules.get(None, Some("radiology"));
+        assert_eq!(rule.nlm_id, None);
+        assert_eq!(rule.headings, vec!["Background", "Purpose", "Results"]);
+        assert_eq!(rule.apply("Text ©RSNA, 2025. See also"), "Text");
+        assert_eq!(rule.apply("Text. Published 2025."), "Text.");
+        assert_eq!(rule.max_authors, 3);
+        assert_eq!(rules.get(None, None).max_authors, 1);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(FormatRules::from_toml("[[journal]]\nheadings = []").is_err());
+        assert!(
+            FormatRules::from_toml(
+                "[[journal]]\nnlm_id = 1\nreplace = [{ regex = '(', replacement = '' }]"
+            )
+            .is_err()
+        );
+    }
+}
@@ -1,123 +1,323 @@
+use chrono::DateTime;
 use regex::{Captures, Regex};
 use rss::Item;
+use serde::{Deserialize, Serialize};
 use std::{borrow::Cow, sync::LazyLock};
+use strum_macros::EnumString;
 use teloxide::{types::ParseMode, utils::markdown};
 
+use crate::channelwrapper::ChannelWrapper;
 use crate::datastructs::ItemMetadata;
+use crate::formatrules::{FormatRule, FormatRules, format_rules};
+use crate::links::{link_templates, select_links};
 
 pub struct PreppedMessage<'a> {
     pub title: String,
+    /// The link of the item, used when the article has no doi.
+    pub link: Option<String>,
     pub journal: Option<String>,
+    pub authors: Vec<String>,
+    /// The publication date, or the epub date for articles ahead of print.
+    pub date: Option<String>,
     pub content: Option<String>,
     pub pmid: Option<String>,
     pub doi: Option<String>,
     pub item_metadata: &'a ItemMetadata,
+    pub format_rule: &'a FormatRule,
 }
 
+/// The output format a `PreppedMessage` is rendered to.
+/// Every sender picks the format that suits its medium.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum MessageFormat {
+    /// Telegram MarkdownV2, with all reserved characters escaped.
+    #[default]
+    MarkdownV2,
+    /// Text without any markup, eg. for sms, console or email fallback.
+    PlainText,
+    /// CommonMark, eg. for notes apps or git based journals.
+    CommonMark,
+}
+
+impl TryFrom<ParseMode> for MessageFormat {
+    type Error = &'static str;
+
+    fn try_from(parsemode: ParseMode) -> Result<Self, Self::Error> {
+        match parsemode {
+            ParseMode::MarkdownV2 => Ok(MessageFormat::MarkdownV2),
+            _ => Err("Only MarkdownV2 is supported as telegram parse mode."),
+        }
+    }
+}
+
+/// How much of an article is shown in a message.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default, EnumString)]
+pub enum MessageStyle {
+    /// Only the title, journal and links.
+    #[strum(ascii_case_insensitive)]
+    Compact,
+    /// The title, journal, links and the start of the abstract.
+    #[strum(ascii_case_insensitive)]
+    Standard,
+    /// The title, journal, links and the complete abstract.
+    #[default]
+    #[strum(ascii_case_insensitive)]
+    Full,
+    /// A user defined template with placeholders: {title}, {journal}, {authors},
+    /// {date}, {doi}, {pmid}, {abstract} and {links}.
+    #[strum(disabled)]
+    Template(String),
+}
+
+/// Number of characters of the abstract shown with `MessageStyle::Standard`.
+const STANDARD_ABSTRACT_LENGTH: usize = 400;
+
 // We make a lazyLock of a struct with our compiled regex queries.
 // That way we don'nt need to recompile the regex query every time.
 // https://doc.rust-lang.org/std/sync/struct.LazyLock.html
-static REGEXSTRUCT: LazyLock<RegexStruct> = LazyLock::new(|| RegexStruct::new());
+static REGEXSTRUCT: LazyLock<RegexStruct> = LazyLock::new(RegexStruct::new);
 
 enum RegexFilter {
-    RemoveItalicKeyword,
-    BoldKeyword,
     CapitalizeKeyword,
     Bold,
     Italic,
+    Unescape,
 }
 
 struct RegexStruct {
-    pub remove_italic_keyword_re: Regex,
-    pub bold_keyword_re: Regex,
     pub capital_keyword_re: Regex,
     pub bold_re: Regex,
     pub italic_re: Regex,
+    pub unescape_re: Regex,
 }
 
 impl RegexStruct {
     fn new() -> RegexStruct {
         log::debug!("Initializing RegexStruct. This should only happen once.");
         RegexStruct {
-            remove_italic_keyword_re: Regex::new(r"(?m)(^|\w|\.)\\_\\_([A-Za-z ]+?)[:.]\\_\\_(\w)").unwrap(),
-            bold_keyword_re: Regex::new(r"(\.|^) ?(Background|Objective|Purpose|Materials and Methods|Results|Conclusion|Clinical Impact|Evidence Synthesis|Evidence Acquisition)[:.]? ?([A-Z])").unwrap(),
             capital_keyword_re: Regex::new(r"(?m)(^|\.) ?([A-Z ]+:) ").unwrap(),
             bold_re: Regex::new(r"(?m)\*\*(.+?)\*\*").unwrap(),
             italic_re: Regex::new(r"(?m)\*(.+?)\*").unwrap(),
+            unescape_re: Regex::new(r"\\([\\`*_{}\[\]()#+\-.!<>|])").unwrap(),
         }
     }
-    pub fn apply<'a>(&self, text: &'a str, filter: RegexFilter) -> Cow<'a, str> {
+    pub fn apply<'a>(
+        &self,
+        text: &'a str,
+        filter: RegexFilter,
+        format: MessageFormat,
+    ) -> Cow<'a, str> {
         match filter {
-            RegexFilter::RemoveItalicKeyword => self
-                .remove_italic_keyword_re
-                .replace_all(text, |caps: &Captures| -> String {
-                    format!("{} {}: {}", &caps[1], &caps[2], &caps[3])
-                }),
-            RegexFilter::BoldKeyword => self
-                .bold_keyword_re // DONT FORGET TO TRIM
-                .replace_all(text, |caps: &Captures| -> String {
-                    format!(
-                        "{}\n\n{} {}",
-                        &caps[1],
-                        markdown::bold(&(caps[2].to_uppercase() + ":")),
-                        &caps[3]
-                    )
-                }),
             RegexFilter::CapitalizeKeyword => {
                 self.capital_keyword_re
                     .replace_all(text, |caps: &Captures| -> String {
                         // DONT FORGET TO TRIM
-                        format!("{}\n{} ", &caps[1], markdown::bold(&caps[2]))
+                        format!("{}\n{} ", &caps[1], bold(&caps[2], format))
                     })
             }
-            RegexFilter::Bold => self.bold_re.replace_all(text, |caps: &Captures| -> String {
-                markdown::bold(&caps[1])
-            }),
+            RegexFilter::Bold => self
+                .bold_re
+                .replace_all(text, |caps: &Captures| -> String { bold(&caps[1], format) }),
             RegexFilter::Italic => self
                 .italic_re
                 .replace_all(text, |caps: &Captures| -> String {
-                    markdown::italic(&caps[1])
+                    italic(&caps[1], format)
                 }),
+            RegexFilter::Unescape => self.unescape_re.replace_all(text, "$1"),
+        }
+    }
+}
+
+/// Puts the headings of a structured abstract on a new line, in bold and uppercase.
+fn bold_headings<'t>(text: &'t str, rule: &FormatRule, format: MessageFormat) -> Cow<'t, str> {
+    match &rule.headings_re {
+        Some(re) => re.replace_all(text, |caps: &Captures| -> String {
+            format!(
+                "{}\n\n{} {}",
+                &caps[1],
+                bold(&(caps[2].to_uppercase() + ":"), format),
+                &caps[3]
+            )
+        }),
+        None => Cow::Borrowed(text),
+    }
+}
+
+fn bold(text: &str, format: MessageFormat) -> String {
+    match format {
+        MessageFormat::MarkdownV2 => markdown::bold(text),
+        MessageFormat::PlainText => text.to_string(),
+        MessageFormat::CommonMark => format!("**{}**", text),
+    }
+}
+
+fn italic(text: &str, format: MessageFormat) -> String {
+    match format {
+        MessageFormat::MarkdownV2 => markdown::italic(text),
+        MessageFormat::PlainText => text.to_string(),
+        MessageFormat::CommonMark => format!("*{}*", text),
+    }
+}
+
+/// Escapes the characters that would otherwise be parsed as CommonMark inline markup.
+fn escape_commonmark(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>') {
+            escaped.push('\\');
         }
+        escaped.push(c);
     }
+    escaped
+}
+
+fn escape(text: &str, format: MessageFormat) -> String {
+    match format {
+        MessageFormat::MarkdownV2 => markdown::escape(text),
+        MessageFormat::PlainText => text.to_string(),
+        MessageFormat::CommonMark => escape_commonmark(text),
+    }
+}
+
+/// Puts already escaped MarkdownV2 text in an expandable blockquote, so telegram
+/// only shows the first lines until the user taps on it.
+/// Every line needs to start with a '>', including the empty lines between sections.
+fn expandable_blockquote(text: &str) -> String {
+    if text.is_empty() {
+        return String::new();
+    }
+    let mut quote = String::new();
+    for line in text.lines() {
+        quote.push('>');
+        quote.push_str(line);
+        quote.push('\n');
+    }
+    quote.pop();
+    quote.push_str("||");
+    quote
+}
+
+/// Highlights the keywords in already formatted text: underlined in MarkdownV2 and
+/// bold in CommonMark. Only whole words are highlighted, and never next to existing
+/// markup, so the highlights can not break the section headings or italic text.
+fn highlight_keywords(text: &str, keywords: &[String], format: MessageFormat) -> String {
+    let (marker, conflicting) = match format {
+        MessageFormat::MarkdownV2 => ("__", '_'),
+        MessageFormat::CommonMark => ("**", '*'),
+        MessageFormat::PlainText => return text.to_string(),
+    };
+    let mut keywords: Vec<String> = keywords
+        .iter()
+        .filter(|k| !k.trim().is_empty())
+        .map(|k| regex::escape(&escape(k.trim(), format)))
+        .collect();
+    if keywords.is_empty() {
+        return text.to_string();
+    }
+    // Prefer the longest match when keywords overlap.
+    keywords.sort_by_key(|k| std::cmp::Reverse(k.len()));
+    let Ok(re) = Regex::new(&format!("(?i){}", keywords.join("|"))) else {
+        return text.to_string();
+    };
+    re.replace_all(text, |caps: &Captures| -> String {
+        let m = caps.get(0).unwrap();
+        let before = text[..m.start()].chars().next_back();
+        let after = text[m.end()..].chars().next();
+        let blocked = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == conflicting);
+        if blocked(before) || blocked(after) {
+            m.as_str().to_string()
+        } else {
+            format!("{}{}{}", marker, m.as_str(), marker)
+        }
+    })
+    .into_owned()
+}
+
+/// Cuts the text at the last whitespace before `max_chars` characters.
+fn shorten(text: &str, max_chars: usize) -> Cow<'_, str> {
+    match text.char_indices().nth(max_chars) {
+        Some((cutoff, _)) => {
+            let cutoff = text[..cutoff].rfind(char::is_whitespace).unwrap_or(cutoff);
+            Cow::Owned(format!("{}…", text[..cutoff].trim_end()))
+        }
+        None => Cow::Borrowed(text),
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace(r"&lt;", "<")
+        .replace(r"&gt;", ">")
+        .replace(r"&amp;", "&")
 }
 
 impl<'a> PreppedMessage<'a> {
     pub fn build(item: &Item, item_metadata: &'a ItemMetadata) -> PreppedMessage<'a> {
+        Self::build_with_rules(item, item_metadata, format_rules())
+    }
+
+    pub fn build_with_rules(
+        item: &Item,
+        item_metadata: &'a ItemMetadata,
+        format_rules: &'a FormatRules,
+    ) -> PreppedMessage<'a> {
         let title = html2md::rewrite_html(item.title().unwrap_or(""), false);
         let mut content = None;
         let journal = Self::extract_journal(item);
+        let format_rule = format_rules.get(item_metadata.feed_id, journal.as_deref());
 
         let content_formatted = html2md::rewrite_html(item.content().unwrap_or(""), false);
         // .replace("**", "*");
         log::debug!("{}", content_formatted);
 
         let abstr_start = content_formatted.find("**ABSTRACT**\n");
-        let pmid_start = content_formatted.find("PMID:[").unwrap_or(0);
-        if abstr_start.is_some() && pmid_start > 0 {
-            content = Some(
-                content_formatted[abstr_start.unwrap() + 13..pmid_start]
-                    .trim()
-                    .to_string(),
-            );
+        let pmid_start = content_formatted.find("PMID:[");
+        if let (Some(abstr_start), Some(pmid_start)) = (abstr_start, pmid_start)
+            && let Some(abstr) = content_formatted.get(abstr_start + 13..pmid_start)
+        {
+            content = Some(format_rule.apply(abstr.trim()).trim().to_string());
+        } else if !ChannelWrapper::is_pubmed_item(item) {
+            // Other feeds: the whole content, or the summary
+            let text = item.content().or(item.description()).unwrap_or("");
+            let text = html2md::rewrite_html(text, false);
+            if !text.trim().is_empty() {
+                content = Some(format_rule.apply(text.trim()).trim().to_string());
+            }
         }
 
         let (mut pmid, mut doi) = (None, None);
-        let identifiers = item.dublin_core_ext().unwrap().identifiers();
+        let identifiers = item
+            .dublin_core_ext()
+            .map(|dc| dc.identifiers())
+            .unwrap_or_default();
         for id in identifiers {
-            if id.contains("pmid:") {
-                pmid = Some(id[5..].to_string());
-            } else if id.contains("doi:") {
-                doi = Some(id[4..].to_string());
+            if let Some(id) = id.strip_prefix("pmid:") {
+                pmid = Some(id.to_string());
+            } else if let Some(id) = id
+                .strip_prefix("doi:")
+                .or(id.strip_prefix("info:doi/"))
+                .or(id.starts_with("10.").then_some(id.as_str()))
+            {
+                doi = Some(id.to_string());
             }
         }
+        if identifiers.is_empty() {
+            log::debug!(
+                "Item without identifiers in feed {:?}: {}",
+                item_metadata.feed_id,
+                title
+            );
+        }
         PreppedMessage {
             title,
+            link: item.link().map(|link| link.to_string()),
             journal,
+            authors: Self::extract_authors(item),
+            date: Self::extract_date(item),
             content,
             pmid,
             doi,
             item_metadata,
+            format_rule,
         }
     }
 
@@ -130,77 +330,344 @@ impl<'a> PreppedMessage<'a> {
             .cloned()
     }
 
-    fn format_link_markdownv2(text: &str, baseurl: &str, pmid_or_doi: &str) -> String {
-        markdown::link(
-            &markdown::escape(&format!("{}{}", baseurl, pmid_or_doi)),
-            &markdown::escape(text),
-        )
+    fn extract_authors(item: &Item) -> Vec<String> {
+        item.dublin_core_ext()
+            .map(|dc| dc.creators().to_vec())
+            .unwrap_or_default()
+    }
+
+    fn extract_date(item: &Item) -> Option<String> {
+        if let Some(date) = item.dublin_core_ext().and_then(|dc| dc.dates().first()) {
+            return Some(date.clone());
+        }
+        let pub_date = DateTime::parse_from_rfc2822(item.pub_date()?).ok()?;
+        Some(pub_date.format("%Y-%m-%d").to_string())
+    }
+
+    /// The first authors (as set in the formatting rules) followed by "et al.".
+    fn format_authors(&self) -> Option<String> {
+        let max_authors = self.format_rule.max_authors;
+        if self.authors.is_empty() || max_authors == 0 {
+            return None;
+        }
+        if self.authors.len() > max_authors {
+            Some(format!("{} et al.", self.authors[..max_authors].join(", ")))
+        } else {
+            Some(self.authors.join(", "))
+        }
+    }
+
+    /// The line with the authors and date below the journal, unescaped.
+    fn format_byline(&self) -> Option<String> {
+        let byline: Vec<String> = self
+            .format_authors()
+            .into_iter()
+            .chain(self.date.clone())
+            .collect();
+        if byline.is_empty() {
+            None
+        } else {
+            Some(byline.join(" · "))
+        }
+    }
+
+    fn format_link_markdownv2(text: &str, url: &str) -> String {
+        markdown::link(&markdown::escape(url), &markdown::escape(text))
     }
 
     fn format_as_markdownv2(&self) -> String {
-        let mut result = "".to_string();
-        let mut footer;
-        if let Some(doi) = &self.doi {
-            result.push_str(&PreppedMessage::format_link_markdownv2(
-                &Self::format_title(&self.title, ParseMode::MarkdownV2),
-                "https://doi.org/",
-                doi,
+        let title = self.highlight(
+            &markdown::escape(&Self::format_title(&self.title, ParseMode::MarkdownV2)),
+            MessageFormat::MarkdownV2,
+        );
+        let mut result = match self.title_url() {
+            // Not format_link_markdownv2: the highlights in the title should not be escaped.
+            Some(url) => markdown::link(&markdown::escape(&url), &title),
+            None => title,
+        };
+        if let Some(journal) = &self.journal {
+            result.push('\n');
+            result.push_str(&markdown::italic(&markdown::escape(journal)));
+        }
+        if let Some(byline) = self.format_byline() {
+            result.push('\n');
+            result.push_str(&markdown::escape(&byline));
+        }
+        if let Some(content) = self.styled_content() {
+            result.push_str("\n\n");
+            result.push_str(&expandable_blockquote(
+                &self.format_content(&content, MessageFormat::MarkdownV2),
+            ));
+        }
+        let footer = self.format_footer(MessageFormat::MarkdownV2);
+        if !footer.is_empty() {
+            result.push('\n');
+            result.push_str(&footer);
+        }
+        log::debug!("{}", result);
+        result
+    }
+
+    /// The doi link of the article, or the link of the item if it has no doi.
+    fn title_url(&self) -> Option<String> {
+        match &self.doi {
+            Some(doi) => Some(format!("https://doi.org/{}", doi)),
+            None => self.link.clone(),
+        }
+    }
+
+    fn format_link_commonmark(text: &str, url: &str) -> String {
+        // Parentheses would end the link destination prematurely.
+        let url = url.replace('(', "%28").replace(')', "%29");
+        format!(
+            "[{}]({})",
+            text.replace('[', r"\[").replace(']', r"\]"),
+            url
+        )
+    }
+
+    fn format_as_commonmark(&self) -> String {
+        let mut result = String::new();
+        match self.title_url() {
+            Some(url) => result.push_str(&PreppedMessage::format_link_commonmark(
+                &self.highlight(&self.title, MessageFormat::CommonMark),
+                &url,
+            )),
+            None => result.push_str(&self.highlight(&self.title, MessageFormat::CommonMark)),
+        }
+        if let Some(journal) = &self.journal {
+            result.push('\n');
+            result.push_str(&italic(
+                &escape_commonmark(journal),
+                MessageFormat::CommonMark,
             ));
+        }
+        if let Some(byline) = self.format_byline() {
+            result.push('\n');
+            result.push_str(&escape_commonmark(&byline));
+        }
+        if let Some(content) = self.styled_content() {
+            result.push_str("\n\n");
+            result.push_str(&self.format_content(&content, MessageFormat::CommonMark));
+        }
+        let footer = self.format_footer(MessageFormat::CommonMark);
+        if !footer.is_empty() {
+            result.push_str("\n\n");
+            result.push_str(&footer);
+        }
+        result
+    }
+
+    fn format_as_plaintext(&self) -> String {
+        let mut result = self.format_title_plaintext();
+        if let Some(journal) = &self.journal {
             result.push('\n');
-            if let Some(journal) = &self.journal {
-                result.push_str(&markdown::italic(&markdown::escape(journal)));
+            result.push_str(journal);
+        }
+        if let Some(byline) = self.format_byline() {
+            result.push('\n');
+            result.push_str(&byline);
+        }
+        if let Some(content) = self.styled_content() {
+            result.push_str("\n\n");
+            result.push_str(&self.format_content(&content, MessageFormat::PlainText));
+        }
+        let footer = self.format_footer(MessageFormat::PlainText);
+        if !footer.is_empty() {
+            result.push_str("\n\n");
+            result.push_str(&footer);
+        }
+        result
+    }
+
+    /// The title without any markup, eg. for citation exports.
+    pub fn plain_title(&self) -> String {
+        self.format_title_plaintext()
+    }
+
+    /// The complete abstract without any markup.
+    pub fn plain_abstract(&self) -> Option<String> {
+        self.content
+            .as_deref()
+            .map(|content| self.format_abstract_text(content, MessageFormat::PlainText))
+    }
+
+    fn format_title_plaintext(&self) -> String {
+        let re = &*REGEXSTRUCT;
+        let mut title = re
+            .apply(
+                &decode_entities(&self.title),
+                RegexFilter::Bold,
+                MessageFormat::PlainText,
+            )
+            .into_owned();
+        title = re
+            .apply(&title, RegexFilter::Italic, MessageFormat::PlainText)
+            .into_owned();
+        re.apply(&title, RegexFilter::Unescape, MessageFormat::PlainText)
+            .into_owned()
+    }
+
+    /// The links picked by the user, with their filled in urls.
+    /// Falls back to the link of the item when none of the links can be filled in.
+    fn footer_links(&self) -> Vec<(&'static str, String)> {
+        let links: Vec<(&'static str, String)> =
+            select_links(link_templates(), self.item_metadata.links.as_deref())
+                .into_iter()
+                .filter_map(|link| {
+                    let url = link.fill(self.doi.as_deref(), self.pmid.as_deref())?;
+                    Some((link.name.as_str(), url))
+                })
+                .collect();
+        match &self.link {
+            Some(link) if links.is_empty() => vec![("Link", link.clone())],
+            _ => links,
+        }
+    }
+
+    fn format_footer(&self, format: MessageFormat) -> String {
+        let links = self.footer_links();
+        match format {
+            MessageFormat::MarkdownV2 => {
+                let mut footer = links
+                    .iter()
+                    .map(|(name, url)| PreppedMessage::format_link_markdownv2(name, url))
+                    .collect::<Vec<String>>()
+                    .join(" \\| ");
+                if self.pmid.is_some() {
+                    for metadata in self.format_metadata_text() {
+                        footer.push_str(&markdown::escape(&format!(" | {}", metadata)));
+                    }
+                }
+                footer
             }
-            if let Some(content) = &self.content {
-                result.push_str("\n\n");
-                result.push_str(&PreppedMessage::format_abstract(
-                    &markdown::escape(content),
-                    ParseMode::MarkdownV2,
-                ));
+            MessageFormat::CommonMark => {
+                let mut footer: Vec<String> = links
+                    .iter()
+                    .map(|(name, url)| PreppedMessage::format_link_commonmark(name, url))
+                    .collect();
+                footer.extend(
+                    self.format_metadata_text()
+                        .iter()
+                        .map(|s| escape_commonmark(s)),
+                );
+                footer.join(" | ")
             }
-            footer = PreppedMessage::format_link_markdownv2("Link", "https://doi.org/", doi);
-
-            if let Some(pmid) = &self.pmid {
-                footer.push_str(&format!(
-                    " \\| {} \\| {}",
-                    &PreppedMessage::format_link_markdownv2(
-                        "PubMed",
-                        "https://pubmed.ncbi.nlm.nih.gov/",
-                        pmid
-                    ),
-                    &PreppedMessage::format_link_markdownv2("QxMD", "https://qxmd.com/r/", pmid)
-                ));
-                if let Some(coll_number) = self.item_metadata.collection {
-                    footer.push_str(&markdown::escape(&format!(" | collection {}", coll_number)));
-                }
-                if let Some(keyword) = self.item_metadata.keyword.as_ref() {
-                    footer.push_str(&markdown::escape(&format!(" | KW: {}", keyword)));
+            MessageFormat::PlainText => {
+                let mut footer: Vec<String> = links
+                    .iter()
+                    .map(|(name, url)| format!("{}: {}", name, url))
+                    .collect();
+                let metadata = self.format_metadata_text();
+                if !metadata.is_empty() {
+                    footer.push(metadata.join(" | "));
                 }
+                footer.join("\n")
             }
-            result.push('\n');
-            result.push_str(&footer);
-            log::debug!("{}", result);
-            result
-        } else {
-            result.push_str(&markdown::escape(&self.title));
-            if let Some(journal) = &self.journal {
-                result.push_str(&markdown::italic(&markdown::escape(journal)));
+        }
+    }
+
+    fn format_metadata_text(&self) -> Vec<String> {
+        let mut metadata = Vec::new();
+        if let Some(coll_number) = self.item_metadata.collection {
+            metadata.push(format!("collection {}", coll_number));
+        }
+        if let Some(keyword) = self.item_metadata.keyword.as_ref() {
+            metadata.push(format!("KW: {}", keyword));
+        }
+        metadata
+    }
+
+    /// The abstract, shortened or left out depending on the message style.
+    fn styled_content(&self) -> Option<Cow<'_, str>> {
+        let content = self.content.as_deref()?;
+        match self.item_metadata.style {
+            MessageStyle::Compact => None,
+            MessageStyle::Standard => Some(shorten(content, STANDARD_ABSTRACT_LENGTH)),
+            MessageStyle::Full | MessageStyle::Template(_) => Some(Cow::Borrowed(content)),
+        }
+    }
+
+    fn format_content(&self, content: &str, format: MessageFormat) -> String {
+        let content = match format {
+            MessageFormat::MarkdownV2 => {
+                self.format_abstract(&markdown::escape(content), ParseMode::MarkdownV2)
             }
-            if let Some(content) = &self.content {
-                result.push_str("\n\n");
-                result.push_str(&markdown::escape(content));
+            _ => self.format_abstract_text(content, format),
+        };
+        self.highlight(&content, format)
+    }
+
+    /// Highlights the whitelist keywords the item matched.
+    fn highlight(&self, text: &str, format: MessageFormat) -> String {
+        highlight_keywords(text, &self.item_metadata.highlights, format)
+    }
+
+    /// Fills in the placeholders of a template. The text around the placeholders
+    /// is escaped, so a template can never produce an invalid message.
+    fn format_template(&self, template: &str, format: MessageFormat) -> String {
+        let mut result = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            let end = start + len + 1;
+            match self.format_placeholder(&rest[start + 1..end - 1], format) {
+                Some(value) => {
+                    result.push_str(&escape(&rest[..start], format));
+                    result.push_str(&value);
+                }
+                None => result.push_str(&escape(&rest[..end], format)),
             }
-            log::info!("{}", result);
-            result
+            rest = &rest[end..];
         }
+        result.push_str(&escape(rest, format));
+        result
     }
 
-    pub fn format(&self, parsemode: ParseMode) -> String {
-        match parsemode {
-            ParseMode::MarkdownV2 => self.format_as_markdownv2(),
-            _ => panic!(),
+    fn format_placeholder(&self, placeholder: &str, format: MessageFormat) -> Option<String> {
+        let value = match placeholder {
+            "title" => match format {
+                MessageFormat::MarkdownV2 => self.highlight(
+                    &markdown::escape(&Self::format_title(&self.title, ParseMode::MarkdownV2)),
+                    format,
+                ),
+                MessageFormat::PlainText => self.format_title_plaintext(),
+                MessageFormat::CommonMark => self.highlight(&self.title, format),
+            },
+            "journal" => escape(self.journal.as_deref().unwrap_or(""), format),
+            "authors" => escape(&self.format_authors().unwrap_or_default(), format),
+            "date" => escape(self.date.as_deref().unwrap_or(""), format),
+            "doi" => escape(self.doi.as_deref().unwrap_or(""), format),
+            "pmid" => escape(self.pmid.as_deref().unwrap_or(""), format),
+            "abstract" => self
+                .content
+                .as_deref()
+                .map(|content| self.format_content(content, format))
+                .unwrap_or_default(),
+            "links" => self.format_footer(format),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    pub fn render(&self, format: MessageFormat) -> String {
+        match &self.item_metadata.style {
+            MessageStyle::Template(template) => self.format_template(template, format),
+            _ => match format {
+                MessageFormat::MarkdownV2 => self.format_as_markdownv2(),
+                MessageFormat::PlainText => self.format_as_plaintext(),
+                MessageFormat::CommonMark => self.format_as_commonmark(),
+            },
         }
     }
 
+    /// Parse modes without a renderer get plain text.
+    pub fn format(&self, parsemode: ParseMode) -> String {
+        self.render(MessageFormat::try_from(parsemode).unwrap_or(MessageFormat::PlainText))
+    }
+
     fn format_markup(text: &str, parsemode: ParseMode) -> String {
         if parsemode == ParseMode::MarkdownV2 {
             // let mut text = markdown::escape(text);
@@ -211,8 +678,11 @@ impl<'a> PreppedMessage<'a> {
 
             let re = &*REGEXSTRUCT;
 
-            text = re.apply(&text, RegexFilter::Bold).into_owned();
-            re.apply(&text, RegexFilter::Italic).into_owned()
+            text = re
+                .apply(&text, RegexFilter::Bold, MessageFormat::MarkdownV2)
+                .into_owned();
+            re.apply(&text, RegexFilter::Italic, MessageFormat::MarkdownV2)
+                .into_owned()
         } else {
             todo!()
         }
@@ -224,36 +694,60 @@ impl<'a> PreppedMessage<'a> {
         formatted.replace(r"\_", r"")
     }
 
-    fn format_abstract(content: &str, parsemode: ParseMode) -> String {
+    fn format_abstract(&self, content: &str, parsemode: ParseMode) -> String {
         // Formats the abstract (escapes invalid characters, bolds RESULT: etc)
         if parsemode == ParseMode::MarkdownV2 {
             let mut content = content.to_string();
 
             content = Self::format_markup(&content, parsemode);
 
-            // Remove RSNA footer copyright
-            if let Some(rsna_footer) = content.find(" ©RSNA") {
-                content.truncate(rsna_footer)
-            }
-
-            let re = &*REGEXSTRUCT;
-            // For AJR:
-            content = re
-                .apply(&content, RegexFilter::RemoveItalicKeyword)
-                .into_owned();
             // For the journal "Radiology" and Acta radiologica (Sweden)
-            content = re
-                .apply(&content, RegexFilter::BoldKeyword)
+            content = bold_headings(&content, self.format_rule, MessageFormat::MarkdownV2)
                 .trim()
                 .to_string();
 
-            re.apply(&content, RegexFilter::CapitalizeKeyword)
-                .trim()
-                .to_string()
+            let re = &*REGEXSTRUCT;
+            re.apply(
+                &content,
+                RegexFilter::CapitalizeKeyword,
+                MessageFormat::MarkdownV2,
+            )
+            .trim()
+            .to_string()
         } else {
             todo!()
         }
     }
+
+    fn format_abstract_text(&self, content: &str, format: MessageFormat) -> String {
+        // Formats the unescaped abstract as plain text or CommonMark (bolds RESULT: etc)
+        let re = &*REGEXSTRUCT;
+        let mut content = content.to_string();
+        if format == MessageFormat::PlainText {
+            content = decode_entities(&content);
+            content = re.apply(&content, RegexFilter::Bold, format).into_owned();
+            content = re.apply(&content, RegexFilter::Italic, format).into_owned();
+            content = re
+                .apply(&content, RegexFilter::Unescape, format)
+                .into_owned();
+        }
+
+        // For the journal "Radiology" and Acta radiologica (Sweden)
+        content = bold_headings(&content, self.format_rule, format)
+            .trim()
+            .to_string();
+
+        content = re
+            .apply(&content, RegexFilter::CapitalizeKeyword, format)
+            .trim()
+            .to_string();
+
+        // Without markup both keyword filters can match the same heading.
+        while content.contains("\n\n\n") {
+            content = content.replace("\n\n\n", "\n\n");
+        }
+        content
+    }
 }
 
 #[cfg(test)]
@@ -275,16 +769,17 @@ mod tests {
             PreppedMessage::build(item, &ItemMetadata::default()).format(ParseMode::MarkdownV2);
         let result = r"[Quantitative MRI radiomics approach for evaluating muscular alteration in Crohn disease: development of a machine learning\-nomogram composite diagnostic tool](https://doi\.org/10\.1007/s00261\-025\-04896\-x)
 _Abdominal radiology \(New York\)_
+Lin Yu et al\. · 2025\-04\-15
 
-*BACKGROUND:* Emerging evidence underscores smooth muscle hyperplasia and hypertrophy, rather than fibrosis, as the defining characteristics of fibrostenotic lesions in Crohn disease \(CD\)\. However, non\-invasive methods for quantifying these muscular changes have yet to be fully explored\.
-
-*AIMS:* To explore the application value of radiomics based on magnetic resonance imaging \(MRI\) post\-contrast T1\-weighted images to identify muscular alteration in CD lesions with significant inflammation\.
-
-*METHODS:* A total of 68 cases were randomly assigned in this study, with 48 cases allocated to the training dataset and the remaining 20 cases assigned to the independent test dataset\. Radiomic features were extracted and constructed a diagnosis model by univariate analysis and least absolute shrinkage and selection operator \(LASSO\) regression\. Construct a nomogram based on multivariate logistic regression analysis, integrating radiomics signature, MRI features and clinical characteristics\.
-
-*RESULTS:* The radiomics model constructed based on the selected features of the post\-contrasted T1\-weighted images has good diagnostic performance, which yielded a sensitivity of 0\.880, a specificity of 0\.783, and an accuracy of 0\.833 \[AUC \= 0\.856, 95% confidence interval \(CI\) \= 0\.765\-0\.947\]\. Moreover, the nomogram representing the integrated model achieved good discrimination performances, which yielded a sensitivity of 0\.836, a specificity of 0\.892, and an accuracy of 0\.864 \(AUC \= 0\.926, 95% CI \= 0\.865\-0\.988\), and it was better than that of the radiomics model alone\.
-
-*CONCLUSIONS:* The radiomics based on post\-contrasted T1\-weighted images provides additional biomarkers for Crohn disease\. Additionally, integrating DCE\-MRI, radiomics, and clinical data into a comprehensive model significantly improves diagnostic accuracy for identifying muscular alteration\.
+>*BACKGROUND:* Emerging evidence underscores smooth muscle hyperplasia and hypertrophy, rather than fibrosis, as the defining characteristics of fibrostenotic lesions in Crohn disease \(CD\)\. However, non\-invasive methods for quantifying these muscular changes have yet to be fully explored\.
+>
+>*AIMS:* To explore the application value of radiomics based on magnetic resonance imaging \(MRI\) post\-contrast T1\-weighted images to identify muscular alteration in CD lesions with significant inflammation\.
+>
+>*METHODS:* A total of 68 cases were randomly assigned in this study, with 48 cases allocated to the training dataset and the remaining 20 cases assigned to the independent test dataset\. Radiomic features were extracted and constructed a diagnosis model by univariate analysis and least absolute shrinkage and selection operator \(LASSO\) regression\. Construct a nomogram based on multivariate logistic regression analysis, integrating radiomics signature, MRI features and clinical characteristics\.
+>
+>*RESULTS:* The radiomics model constructed based on the selected features of the post\-contrasted T1\-weighted images has good diagnostic performance, which yielded a sensitivity of 0\.880, a specificity of 0\.783, and an accuracy of 0\.833 \[AUC \= 0\.856, 95% confidence interval \(CI\) \= 0\.765\-0\.947\]\. Moreover, the nomogram representing the integrated model achieved good discrimination performances, which yielded a sensitivity of 0\.836, a specificity of 0\.892, and an accuracy of 0\.864 \(AUC \= 0\.926, 95% CI \= 0\.865\-0\.988\), and it was better than that of the radiomics model alone\.
+>
+>*CONCLUSIONS:* The radiomics based on post\-contrasted T1\-weighted images provides additional biomarkers for Crohn disease\. Additionally, integrating DCE\-MRI, radiomics, and clinical data into a comprehensive model significantly improves diagnostic accuracy for identifying muscular alteration\.||
 [Link](https://doi\.org/10\.1007/s00261\-025\-04896\-x) \| [PubMed](https://pubmed\.ncbi\.nlm\.nih\.gov/40232416) \| [QxMD](https://qxmd\.com/r/40232416)";
 
         assert_eq!(message, result);
@@ -299,16 +794,17 @@ _Abdominal radiology \(New York\)_
             PreppedMessage::build(item, &ItemMetadata::default()).format(ParseMode::MarkdownV2);
         let result = r"[Intraprotocol Adrenal Vein Sampling Inconsistencies in Primary Aldosteronism Lateralization](https://doi\.org/10\.1148/radiol\.240631)
 _Radiology_
+Livia M Mermejo et al\. · 2025\-04\-15
 
-*BACKGROUND:* Primary aldosteronism can arise from one or both adrenal glands\. Adrenal vein sampling \(AVS\) is the standard of care for identifying patients with lateralized primary aldosteronism who would benefit from surgery\. Variability in AVS lateralization has been primarily attributed to cosyntropin use and lateralization index thresholds\. Data regarding intraprotocol variability are lacking\.
-
-*PURPOSE:* To assess the rates of intraprotocol lateralization inconsistency during simultaneous AVS\.
-
-*MATERIALS AND METHODS:* This retrospective cross\-sectional study assessed patients with primary aldosteronism who underwent simultaneous AVS at a single tertiary referral center between January 2015 and December 2023\. Six sets of adrenal vein and peripheral vein samples were obtained: three baseline samples obtained after cannulation, 5 minutes apart; and three samples obtained between 5 and 30 minutes after cosyntropin stimulation\. Patients with successful cannulation and valid hormonal data at all six time points were included\. A lateralization index \(computed as the aldosterone\-to\-cortisol ratio between the two adrenal veins, with the highest number as numerator\) of at least 4 was considered indicative of lateralized primary aldosteronism\. The proportions of baseline and stimulated AVS sets within which one of three lateralization indexes provided different subtype results were assessed\. Linear mixed\-effects models were used to estimate the between\- and within\-patient hormonal and lateralization index variances\.
-
-*RESULTS:* Of 402 patients \(median age, 53 years; IQR, 45\-63 years; 233 male\) included, 129 patients \(32\.1%\) had at least one lateralization index inconsistency\. Of these 402 patients, 89 patients \(22\.1%\) had lateralization inconsistencies within the baseline sets, 53 patients \(13\.2%\) within cosyntropin\-stimulated sets, and 13 patients \(3\.2%\) in both baseline and cosyntropin\-stimulated sets\. The highest outlier prevalence occurred in the first \(42 patients; 10\.4%\) and third \(33 patients; 8\.2%\) baseline samples, with roughly twofold\-lower rates in the first \(23 patients; 5\.7%\) and last postcosyntropin stimulation samples \(4\.2%; 17 patients\)\. The absolute change in baseline and cosyntropin\-stimulated lateralization index \(maximum\-minimum lateralization index within a triplicate\) was as high as 152\.9 and 327\.4, respectively\. The highest hormonal variability was noted in the adrenal vein producing less aldosterone\.
-
-*CONCLUSION:* Almost a third of patients undergoing AVS in triplicate, both before and after cosyntropin stimulation, had intraprotocol discrepancies in lateralization results, with the highest variability occurring within samples obtained without cosyntropin stimulation\.
+>*BACKGROUND:* Primary aldosteronism can arise from one or both adrenal glands\. Adrenal vein sampling \(AVS\) is the standard of care for identifying patients with lateralized primary aldosteronism who would benefit from surgery\. Variability in AVS lateralization has been primarily attributed to cosyntropin use and lateralization index thresholds\. Data regarding intraprotocol variability are lacking\.
+>
+>*PURPOSE:* To assess the rates of intraprotocol lateralization inconsistency during simultaneous AVS\.
+>
+>*MATERIALS AND METHODS:* This retrospective cross\-sectional study assessed patients with primary aldosteronism who underwent simultaneous AVS at a single tertiary referral center between January 2015 and December 2023\. Six sets of adrenal vein and peripheral vein samples were obtained: three baseline samples obtained after cannulation, 5 minutes apart; and three samples obtained between 5 and 30 minutes after cosyntropin stimulation\. Patients with successful cannulation and valid hormonal data at all six time points were included\. A lateralization index \(computed as the aldosterone\-to\-cortisol ratio between the two adrenal veins, with the highest number as numerator\) of at least 4 was considered indicative of lateralized primary aldosteronism\. The proportions of baseline and stimulated AVS sets within which one of three lateralization indexes provided different subtype results were assessed\. Linear mixed\-effects models were used to estimate the between\- and within\-patient hormonal and lateralization index variances\.
+>
+>*RESULTS:* Of 402 patients \(median age, 53 years; IQR, 45\-63 years; 233 male\) included, 129 patients \(32\.1%\) had at least one lateralization index inconsistency\. Of these 402 patients, 89 patients \(22\.1%\) had lateralization inconsistencies within the baseline sets, 53 patients \(13\.2%\) within cosyntropin\-stimulated sets, and 13 patients \(3\.2%\) in both baseline and cosyntropin\-stimulated sets\. The highest outlier prevalence occurred in the first \(42 patients; 10\.4%\) and third \(33 patients; 8\.2%\) baseline samples, with roughly twofold\-lower rates in the first \(23 patients; 5\.7%\) and last postcosyntropin stimulation samples \(4\.2%; 17 patients\)\. The absolute change in baseline and cosyntropin\-stimulated lateralization index \(maximum\-minimum lateralization index within a triplicate\) was as high as 152\.9 and 327\.4, respectively\. The highest hormonal variability was noted in the adrenal vein producing less aldosterone\.
+>
+>*CONCLUSION:* Almost a third of patients undergoing AVS in triplicate, both before and after cosyntropin stimulation, had intraprotocol discrepancies in lateralization results, with the highest variability occurring within samples obtained without cosyntropin stimulation\.||
 [Link](https://doi\.org/10\.1148/radiol\.240631) \| [PubMed](https://pubmed\.ncbi\.nlm\.nih\.gov/40232138) \| [QxMD](https://qxmd\.com/r/40232138)";
 
         assert_eq!(message, result);
@@ -326,19 +822,221 @@ _Radiology_
         let message = PreppedMessage::build(item, &item_metadata).format(ParseMode::MarkdownV2);
         let result = r"[Interreader Agreement of Lung\-RADS: A Systematic Review and Meta\-Analysis](https://doi\.org/10\.2214/AJR\.25\.32681)
 _AJR\. American journal of roentgenology_
+Jisun Hwang et al\. · 2025\-04\-09
 
-*BACKGROUND:* Lung\-RADS has shown variable interreader agreement in the literature, in part related to a broad range of factors that may influence the consistency of its implementation\.
+>*BACKGROUND:* Lung\-RADS has shown variable interreader agreement in the literature, in part related to a broad range of factors that may influence the consistency of its implementation\.
+>
+>*OBJECTIVE:* To assess the interreader agreement of Lung\-RADS and to investigate factors influencing the system's variability\.
+>
+>*EVIDENCE ACQUISITION:* EMBASE, PubMed, and Cochrane databases were searched for original research studies published through June 18, 2024 reporting the interreader agreement of Lung\-RADS on chest CT\. Random\-effect models were used to calculate pooled kappa coefficients for Lung\-RADS categorization and pooled intraclass correlation coefficients \(ICCs\) for nodule size measurements\. Potential sources of heterogeneity were explored using metaregression analyses\.
+>
+>*EVIDENCE SYNTHESIS:* The analysis included 11 studies \(1470 patients\) for Lung\-RADS categorization and five studies \(617 patients\) for nodule size measurement\. Interreader agreement for Lung\-RADS categorization was substantial \(κ\=0\.72 \[95% CI, 0\.57\-0\.82\]\), and for nodule size measurement was almost perfect \(ICC\=0\.97 \[95% CI, 0\.90\-0\.99\]\)\. Interreader agreement for Lung\-RADS categorization was significantly associated with the method of nodule measurement \(p\=\.005\), with pooled kappa coefficients for studies using computer\-aided detection \(CAD\)\-based semiautomated volume measurements, using CAD\-based semiautomated diameter measurements, and using manual diameter measurements of 0\.95, 0\.91, and 0\.66, respectively\. Interreader agreement for Lung\-RADS categorization was also significantly associated with studies' nodule type distribution \(p\<\.001\), with pooled kappa coefficients for studies evaluating 100% solid nodules, 30\-99% solid nodules, and \<30% solid nodules of 0\.85, 0\.76, and 0\.55, respectively\. Interreader agreement fornodule size measurement was significantly associated with radiation dose \(p\<\.001\), with pooled ICCs for studies that used standard\-dose CT, used low\-dose CT, and used ultralow\-dose CT of 0\.97, 0\.96, and 0\.59, respectively\. Interreader agreement for nodule size measurement was also significantly associated with the Lung\-RADS version used \(p\=\.02\), with pooled ICCs for studies using Lung\-RADS 1\.1 and using Lung\-RADS 1\.0 of 0\.99 and 0\.93, respectively\.
+>
+>*CONCLUSION:* While supporting the overall reliability of Lung\-RADS, the findings indicate roles for CAD assistance as well as training and standardized approaches for nodule type characterization to further promote reproducible application\.
+>
+>*CLINICAL IMPACT:* Consistent nodule assessments will be critical for Lung\-RADS to optimally impact patient management and outcomes\.||
+[Link](https://doi\.org/10\.2214/AJR\.25\.32681) \| [PubMed](https://pubmed\.ncbi\.nlm\.nih\.gov/40202356) \| [QxMD](https://qxmd\.com/r/40202356) \| collection 3";
+        assert_eq!(message, result);
+    }
 
-*OBJECTIVE:* To assess the interreader agreement of Lung\-RADS and to investigate factors influencing the system's variability\.
+    #[test]
+    fn test_format_plaintext() {
+        let mut file = File::open("test/channel_AJR.json").unwrap();
+        let mut json = String::new();
+        file.read_to_string(&mut json).unwrap();
+        let channel = ChannelWrapper::from_json(&json).unwrap();
 
-*EVIDENCE ACQUISITION:* EMBASE, PubMed, and Cochrane databases were searched for original research studies published through June 18, 2024 reporting the interreader agreement of Lung\-RADS on chest CT\. Random\-effect models were used to calculate pooled kappa coefficients for Lung\-RADS categorization and pooled intraclass correlation coefficients \(ICCs\) for nodule size measurements\. Potential sources of heterogeneity were explored using metaregression analyses\.
+        let item = &channel.items[0];
+        let item_metadata = ItemMetadata {
+            collection: Some(3),
+            ..Default::default()
+        };
+        let message = PreppedMessage::build(item, &item_metadata).render(MessageFormat::PlainText);
+        assert!(message.starts_with(
+            "Interreader Agreement of Lung-RADS: A Systematic Review and Meta-Analysis
+AJR. American journal of roentgenology
+Jisun Hwang et al. · 2025-04-09
 
-*EVIDENCE SYNTHESIS:* The analysis included 11 studies \(1470 patients\) for Lung\-RADS categorization and five studies \(617 patients\) for nodule size measurement\. Interreader agreement for Lung\-RADS categorization was substantial \(κ\=0\.72 \[95% CI, 0\.57\-0\.82\]\), and for nodule size measurement was almost perfect \(ICC\=0\.97 \[95% CI, 0\.90\-0\.99\]\)\. Interreader agreement for Lung\-RADS categorization was significantly associated with the method of nodule measurement \(p\=\.005\), with pooled kappa coefficients for studies using computer\-aided detection \(CAD\)\-based semiautomated volume measurements, using CAD\-based semiautomated diameter measurements, and using manual diameter measurements of 0\.95, 0\.91, and 0\.66, respectively\. Interreader agreement for Lung\-RADS categorization was also significantly associated with studies' nodule type distribution \(p\<\.001\), with pooled kappa coefficients for studies evaluating 100% solid nodules, 30\-99% solid nodules, and \<30% solid nodules of 0\.85, 0\.76, and 0\.55, respectively\. Interreader agreement fornodule size measurement was significantly associated with radiation dose \(p\<\.001\), with pooled ICCs for studies that used standard\-dose CT, used low\-dose CT, and used ultralow\-dose CT of 0\.97, 0\.96, and 0\.59, respectively\. Interreader agreement for nodule size measurement was also significantly associated with the Lung\-RADS version used \(p\=\.02\), with pooled ICCs for studies using Lung\-RADS 1\.1 and using Lung\-RADS 1\.0 of 0\.99 and 0\.93, respectively\.
+BACKGROUND: Lung-RADS has shown variable interreader agreement"
+        ));
+        assert!(message.contains(
+            "implementation.\n\nOBJECTIVE: To assess the interreader agreement of Lung-RADS"
+        ));
+        assert!(message.contains("nodule type distribution (p<.001)"));
+        assert!(!message.contains("\n\n\n"));
+        assert!(!message.contains('*'));
+        assert!(!message.contains('\\'));
+        assert!(message.ends_with(
+            "outcomes.
 
-*CONCLUSION:* While supporting the overall reliability of Lung\-RADS, the findings indicate roles for CAD assistance as well as training and standardized approaches for nodule type characterization to further promote reproducible application\.
+Link: https://doi.org/10.2214/AJR.25.32681
+PubMed: https://pubmed.ncbi.nlm.nih.gov/40202356
+QxMD: https://qxmd.com/r/40202356
+collection 3"
+        ));
+        // Parse modes without a renderer do not panic
+        assert!(MessageFormat::try_from(ParseMode::Html).is_err());
+        assert_eq!(
+            PreppedMessage::build(item, &item_metadata).format(ParseMode::Html),
+            message
+        );
+    }
 
-*CLINICAL IMPACT:* Consistent nodule assessments will be critical for Lung\-RADS to optimally impact patient management and outcomes\.
-[Link](https://doi\.org/10\.2214/AJR\.25\.32681) \| [PubMed](https://pubmed\.ncbi\.nlm\.nih\.gov/40202356) \| [QxMD](https://qxmd\.com/r/40202356) \| collection 3";
+    #[test]
+    fn test_format_commonmark() {
+        let mut file = File::open("test/channel_radiology.json").unwrap();
+        let mut json = String::new();
+        file.read_to_string(&mut json).unwrap();
+        let channel = ChannelWrapper::from_json(&json).unwrap();
+
+        let item = &channel.items[7];
+        let message =
+            PreppedMessage::build(item, &ItemMetadata::default()).render(MessageFormat::CommonMark);
+        assert!(message.starts_with(
+            "[Intraprotocol Adrenal Vein Sampling Inconsistencies in Primary Aldosteronism Lateralization](https://doi.org/10.1148/radiol.240631)
+*Radiology*
+Livia M Mermejo et al. · 2025-04-15
+
+**BACKGROUND:** Primary aldosteronism"
+        ));
+        assert!(message.contains("are lacking.\n\n**PURPOSE:** To assess"));
+        assert!(!message.contains("©RSNA"));
+        assert!(message.ends_with(
+            "stimulation.
+
+[Link](https://doi.org/10.1148/radiol.240631) | [PubMed](https://pubmed.ncbi.nlm.nih.gov/40232138) | [QxMD](https://qxmd.com/r/40232138)"
+        ));
+
+        let item_metadata = ItemMetadata {
+            links: Some(vec!["QxMD".to_string(), "Link".to_string()]),
+            ..Default::default()
+        };
+        let message = PreppedMessage::build(item, &item_metadata).render(MessageFormat::CommonMark);
+        assert!(message.ends_with(
+            "\n\n[QxMD](https://qxmd.com/r/40232138) | [Link](https://doi.org/10.1148/radiol.240631)"
+        ));
+    }
+
+    #[test]
+    fn test_format_styles() {
+        let mut file = File::open("test/channel_abdominal_radiology.json").unwrap();
+        let mut json = String::new();
+        file.read_to_string(&mut json).unwrap();
+        let channel = ChannelWrapper::from_json(&json).unwrap();
+        let item = &channel.items[0];
+
+        let item_metadata = ItemMetadata {
+            style: MessageStyle::Compact,
+            ..Default::default()
+        };
+        let message = PreppedMessage::build(item, &item_metadata).format(ParseMode::MarkdownV2);
+        let result = r"[Quantitative MRI radiomics approach for evaluating muscular alteration in Crohn disease: development of a machine learning\-nomogram composite diagnostic tool](https://doi\.org/10\.1007/s00261\-025\-04896\-x)
+_Abdominal radiology \(New York\)_
+Lin Yu et al\. · 2025\-04\-15
+[Link](https://doi\.org/10\.1007/s00261\-025\-04896\-x) \| [PubMed](https://pubmed\.ncbi\.nlm\.nih\.gov/40232416) \| [QxMD](https://qxmd\.com/r/40232416)";
         assert_eq!(message, result);
+
+        let item_metadata = ItemMetadata {
+            style: MessageStyle::Standard,
+            ..Default::default()
+        };
+        let message = PreppedMessage::build(item, &item_metadata).render(MessageFormat::PlainText);
+        assert!(message.contains("\n\nBACKGROUND: Emerging evidence"));
+        assert!(message.contains("…\n\nLink: "));
+        assert!(!message.contains("CONCLUSIONS:"));
+
+        let item_metadata = ItemMetadata {
+            style: MessageStyle::Template(
+                "{title} [{journal}] {unknown} PMID {pmid} by {authors}, {date}".to_string(),
+            ),
+            ..Default::default()
+        };
+        let message = PreppedMessage::build(item, &item_metadata).format(ParseMode::MarkdownV2);
+        let result = r"Quantitative MRI radiomics approach for evaluating muscular alteration in Crohn disease: development of a machine learning\-nomogram composite diagnostic tool \[Abdominal radiology \(New York\)\] \{unknown\} PMID 40232416 by Lin Yu et al\., 2025\-04\-15";
+        assert_eq!(message, result);
+    }
+
+    #[test]
+    fn test_highlights() {
+        let mut file = File::open("test/channel_AJR.json").unwrap();
+        let mut json = String::new();
+        file.read_to_string(&mut json).unwrap();
+        let channel = ChannelWrapper::from_json(&json).unwrap();
+        let item = &channel.items[0];
+
+        let item_metadata = ItemMetadata {
+            highlights: vec![
+                "lung-rads".to_string(),
+                "interreader agreement".to_string(),
+                "background".to_string(),
+                "rads".to_string(),
+            ],
+            ..Default::default()
+        };
+        let message = PreppedMessage::build(item, &item_metadata).format(ParseMode::MarkdownV2);
+        assert!(message.starts_with(
+            r"[__Interreader Agreement__ of __Lung\-RADS__: A Systematic Review and Meta\-Analysis](https://doi\.org/10\.2214/AJR\.25\.32681)"
+        ));
+        // Highlights inside a heading stay inside the bold markup
+        assert!(message.contains(
+            r">*__BACKGROUND__:* __Lung\-RADS__ has shown variable __interreader agreement__"
+        ));
+
+        let message = PreppedMessage::build(item, &item_metadata).render(MessageFormat::CommonMark);
+        assert!(message.contains("**BACKGROUND:** **Lung-RADS** has shown variable"));
+
+        let message = PreppedMessage::build(item, &item_metadata).render(MessageFormat::PlainText);
+        assert!(!message.contains("__"));
+        assert!(!message.contains('*'));
+    }
+
+    #[test]
+    fn test_format_without_metadata() {
+        // No dublin core, no doi and no abstract: only the title and the link.
+        let item = rss::ItemBuilder::default()
+            .title(Some("A title".to_string()))
+            .link(Some("https://example.org/article".to_string()))
+            .build();
+        let item_metadata = ItemMetadata::default();
+        let message = PreppedMessage::build(&item, &item_metadata);
+        assert_eq!(
+            message.format(ParseMode::MarkdownV2),
+            "[A title](https://example\\.org/article)\n[Link](https://example\\.org/article)"
+        );
+        assert_eq!(
+            message.render(MessageFormat::PlainText),
+            "A title\n\nLink: https://example.org/article"
+        );
+    }
+
+    #[test]
+    fn test_expandable_blockquote() {
+        assert_eq!(expandable_blockquote(""), "");
+        assert_eq!(expandable_blockquote(r"one line\."), r">one line\.||");
+        assert_eq!(
+            expandable_blockquote("*A:* first\n\n*B:* second"),
+            ">*A:* first\n>\n>*B:* second||"
+        );
+    }
+
+    #[test]
+    fn test_format_rules() {
+        let mut file = File::open("test/channel_radiology.json").unwrap();
+        let mut json = String::new();
+        file.read_to_string(&mut json).unwrap();
+        let channel = ChannelWrapper::from_json(&json).unwrap();
+        let item = &channel.items[7];
+
+        let rules = FormatRules::from_file(std::path::Path::new("test/format_rules.toml")).unwrap();
+        let item_metadata = ItemMetadata::default();
+        let message = PreppedMessage::build_with_rules(item, &item_metadata, &rules)
+            .render(MessageFormat::PlainText);
+        // Only the headings of the journal rule are put on a new line
+        assert!(message.contains("\n\nPURPOSE: To assess"));
+        assert!(message.contains("simultaneous AVS. Materials and Methods This retrospective"));
+        assert!(!message.contains("©RSNA"));
+        assert!(
+            message.contains("\nLivia M Mermejo, Lili Zhao, Chaelin Lee et al. · 2025-04-15\n")
+        );
     }
 }
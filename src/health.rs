@@ -0,0 +1,190 @@
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use crate::datastructs::PubmedFeed;
+
+// The health of a feed, updated on every download. Feeds that keep failing
+// are retried less often: after 1 failure at the next update, then after 2,
+// 4, 8... hours, up to once a day.
+
+/// Just under an hour, as the updates run every hour.
+const BACKOFF_BASE_MINUTES: i64 = 55;
+const BACKOFF_MAX_HOURS: i64 = 24;
+/// A feed without new items for this long is reported as stale.
+pub const STALE_DAYS: i64 = 14;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct FeedHealth {
+    /// Dates are stored in RFC 2822, like `User::last_pushed`.
+    pub last_success: Option<String>,
+    pub failures: u32,
+    pub last_error: Option<String>,
+    pub last_failure: Option<String>,
+    /// Number of items in the last download.
+    pub item_count: Option<u32>,
+    /// When the last item that was not in the previous download was found.
+    pub last_new_item: Option<String>,
+    /// Number of items skipped in the last download because they can not be parsed.
+    #[serde(default)]
+    pub malformed_items: u32,
+}
+
+fn parse_date(date: &Option<String>) -> Option<DateTime<Local>> {
+    date.as_deref()
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+        .map(|date| date.into())
+}
+
+impl FeedHealth {
+    pub fn record_success(&mut self, now: DateTime<Local>, item_count: usize, new_items: bool) {
+        self.last_success = Some(now.to_rfc2822());
+        self.failures = 0;
+        self.item_count = Some(item_count as u32);
+        if new_items {
+            self.last_new_item = Some(now.to_rfc2822());
+        }
+    }
+
+    pub fn record_failure(&mut self, now: DateTime<Local>, error: &str) {
+        self.failures += 1;
+        self.last_error = Some(error.to_string());
+        self.last_failure = Some(now.to_rfc2822());
+    }
+
+    pub fn last_success_date(&self) -> Option<DateTime<Local>> {
+        parse_date(&self.last_success)
+    }
+
+    /// When a failing feed can be downloaded again, None if it is not failing.
+    pub fn retry_after(&self) -> Option<DateTime<Local>> {
+        if self.failures == 0 {
+            return None;
+        }
+        let exponent = (self.failures - 1).min(16);
+        let backoff = TimeDelta::minutes(BACKOFF_BASE_MINUTES * 2i64.pow(exponent))
+            .min(TimeDelta::hours(BACKOFF_MAX_HOURS));
+        parse_date(&self.last_failure).map(|last_failure| last_failure + backoff)
+    }
+
+    pub fn backing_off(&self, now: DateTime<Local>) -> bool {
+        self.retry_after()
+            .is_some_and(|retry_after| now < retry_after)
+    }
+
+    /// The problems of the feed, empty if it is healthy.
+    pub fn problems(&self, now: DateTime<Local>) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.failures > 0 {
+            let mut problem = format!(
+                "failed {} times in a row: {}",
+                self.failures,
+                self.last_error.as_deref().unwrap_or("unknown error")
+            );
+            if let Some(retry_after) = self.retry_after() {
+                problem.push_str(&format!(
+                    " (next try after {})",
+                    retry_after.format("%Y-%m-%d %H:%M")
+                ));
+            }
+            problems.push(problem);
+        }
+        if self.item_count == Some(0) {
+            problems.push("the last download was empty".to_string());
+        }
+        if self.malformed_items > 0 {
+            problems.push(format!(
+                "skipped {} malformed items in the last download",
+                self.malformed_items
+            ));
+        }
+        if self.last_success.is_some() {
+            match parse_date(&self.last_new_item) {
+                Some(last_new_item) if now - last_new_item > TimeDelta::days(STALE_DAYS) => {
+                    problems.push(format!(
+                        "no new items since {}",
+                        last_new_item.format("%Y-%m-%d")
+                    ))
+                }
+                Some(_) => (),
+                None => problems.push("no new items yet".to_string()),
+            }
+        }
+        problems
+    }
+}
+
+/// Lists the stale, empty and failing feeds.
+pub fn health_report(feeds: &[PubmedFeed], now: DateTime<Local>) -> String {
+    let mut report = String::new();
+    for feed in feeds {
+        let problems = feed.health.problems(now);
+        if problems.is_empty() {
+            continue;
+        }
+        report.push_str(&format!(
+            "{} - {} ({} subscribers):\n",
+            feed.uid.map(|uid| uid.to_string()).unwrap_or_default(),
+            feed.name,
+            feed.subscribers
+        ));
+        for problem in problems {
+            report.push_str(&format!("  {}\n", problem));
+        }
+    }
+    if report.is_empty() {
+        return format!("All {} feeds are healthy.", feeds.len());
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed_health() {
+        let now = Local::now();
+        let mut health = FeedHealth::default();
+        assert!(!health.backing_off(now));
+        assert!(health.problems(now).is_empty());
+
+        health.record_success(now - TimeDelta::days(20), 15, true);
+        health.record_success(now, 15, false);
+        assert_eq!(health.problems(now).len(), 1);
+        assert!(health.problems(now)[0].starts_with("no new items since"));
+
+        // 1 failure: retried at the next hourly update
+        health.record_failure(now, "timed out");
+        assert!(health.backing_off(now + TimeDelta::minutes(30)));
+        assert!(!health.backing_off(now + TimeDelta::minutes(60)));
+        // 3 failures: wait 4 hours
+        health.record_failure(now, "timed out");
+        health.record_failure(now, "404 Not Found");
+        assert!(health.backing_off(now + TimeDelta::hours(3)));
+        assert!(!health.backing_off(now + TimeDelta::hours(4)));
+        // Never more than a day
+        for _ in 0..20 {
+            health.record_failure(now, "404 Not Found");
+        }
+        assert!(!health.backing_off(now + TimeDelta::hours(24)));
+        assert!(health.problems(now)[0].starts_with("failed 23 times in a row: 404 Not Found"));
+
+        health.record_success(now, 0, false);
+        assert_eq!(health.failures, 0);
+        assert_eq!(
+            health.problems(now),
+            vec![
+                "the last download was empty".to_string(),
+                "no new items since ".to_string()
+                    + &(now - TimeDelta::days(20)).format("%Y-%m-%d").to_string()
+            ]
+        );
+
+        health.malformed_items = 2;
+        assert!(
+            health
+                .problems(now)
+                .contains(&"skipped 2 malformed items in the last download".to_string())
+        );
+    }
+}
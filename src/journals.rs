@@ -0,0 +1,199 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+// The NLM catalogue of journals, to find the PubMed feed of a journal by its
+// name. The catalogue is read from a file in the format of the MEDLINE
+// journal list (J_Medline.txt, https://ftp.ncbi.nih.gov/pubmed/J_Medline.txt):
+//
+// --------------------------------------------------------
+// JrId: 7007
+// JournalTitle: Radiology
+// MedAbbr: Radiology
+// ISSN (Print): 0033-8419
+// ISSN (Online): 1527-1315
+// IsoAbbr: Radiology
+// NlmId: 0401260
+
+static JOURNAL_CATALOGUE: OnceLock<JournalCatalogue> = OnceLock::new();
+
+/// Number of journals shown by /findjournal.
+pub const MAX_FOUND_JOURNALS: usize = 10;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Journal {
+    pub nlm_id: String,
+    pub title: String,
+    pub med_abbr: Option<String>,
+    pub iso_abbr: Option<String>,
+    pub issn_print: Option<String>,
+    pub issn_online: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct JournalCatalogue {
+    pub journals: Vec<Journal>,
+}
+
+impl Journal {
+    /// The PubMed RSS feed of the journal, with the last 15 articles.
+    pub fn rss_link(&self) -> String {
+        let name = self.med_abbr.as_deref().unwrap_or(&self.title);
+        reqwest::Url::parse_with_params(
+            &format!(
+                "https://pubmed.ncbi.nlm.nih.gov/rss/journals/{}/",
+                self.nlm_id
+            ),
+            &[
+                ("limit", "15"),
+                ("name", name),
+                ("utm_campaign", "journals"),
+            ],
+        )
+        .map(|url| url.to_string())
+        .unwrap_or_default()
+    }
+
+    fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.title.as_str())
+            .chain(self.med_abbr.as_deref())
+            .chain(self.iso_abbr.as_deref())
+    }
+}
+
+impl JournalCatalogue {
+    pub fn from_file(path: &Path) -> io::Result<JournalCatalogue> {
+        Ok(JournalCatalogue::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Journals without an NLM ID or title are skipped.
+    pub fn parse(text: &str) -> JournalCatalogue {
+        let mut journals = Vec::new();
+        let mut journal = Journal::default();
+        for line in text.lines().chain(std::iter::once("-")) {
+            if line.starts_with('-') {
+                if !journal.nlm_id.is_empty() && !journal.title.is_empty() {
+                    journals.push(journal);
+                }
+                journal = Journal::default();
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            let optional = (!value.is_empty()).then(|| value.to_string());
+            match key.trim() {
+                "NlmId" => journal.nlm_id = value.to_string(),
+                "JournalTitle" => journal.title = value.to_string(),
+                "MedAbbr" => journal.med_abbr = optional,
+                "IsoAbbr" => journal.iso_abbr = optional,
+                "ISSN (Print)" => journal.issn_print = optional,
+                "ISSN (Online)" => journal.issn_online = optional,
+                _ => (),
+            }
+        }
+        JournalCatalogue { journals }
+    }
+
+    pub fn get(&self, nlm_id: &str) -> Option<&Journal> {
+        self.journals
+            .iter()
+            .find(|journal| journal.nlm_id.eq_ignore_ascii_case(nlm_id))
+    }
+
+    /// The journals with the NLM ID or ISSN, or with all the words in their
+    /// title or abbreviations. Exact matches of a name come first, then the
+    /// names that start with the search, then the shortest titles.
+    pub fn find(&self, search: &str) -> Vec<&Journal> {
+        let search = search.trim().to_lowercase();
+        if search.is_empty() {
+            return Vec::new();
+        }
+        let by_id: Vec<&Journal> = self
+            .journals
+            .iter()
+            .filter(|journal| {
+                journal.nlm_id.eq_ignore_ascii_case(&search)
+                    || [&journal.issn_print, &journal.issn_online]
+                        .iter()
+                        .any(|issn| {
+                            issn.as_deref()
+                                .is_some_and(|issn| issn.eq_ignore_ascii_case(&search))
+                        })
+            })
+            .collect();
+        if !by_id.is_empty() {
+            return by_id;
+        }
+        let words: Vec<&str> = search.split_whitespace().collect();
+        let mut found: Vec<(u8, &Journal)> = self
+            .journals
+            .iter()
+            .filter_map(|journal| {
+                let names: Vec<String> = journal.names().map(|name| name.to_lowercase()).collect();
+                if !words
+                    .iter()
+                    .all(|word| names.iter().any(|name| name.contains(word)))
+                {
+                    return None;
+                }
+                let rank = if names.contains(&search) {
+                    0
+                } else if names.iter().any(|name| name.starts_with(&search)) {
+                    1
+                } else {
+                    2
+                };
+                Some((rank, journal))
+            })
+            .collect();
+        found.sort_by_key(|(rank, journal)| (*rank, journal.title.len()));
+        found.into_iter().map(|(_, journal)| journal).collect()
+    }
+}
+
+/// Sets the catalogue read at startup. Can only be set once.
+pub fn set_journal_catalogue(catalogue: JournalCatalogue) -> Result<(), &'static str> {
+    JOURNAL_CATALOGUE
+        .set(catalogue)
+        .map_err(|_| "The journal catalogue was already set!")
+}
+
+/// The catalogue set at startup, or an empty one.
+pub fn journal_catalogue() -> &'static JournalCatalogue {
+    JOURNAL_CATALOGUE.get_or_init(JournalCatalogue::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalogue() {
+        let catalogue = JournalCatalogue::from_file(Path::new("test/J_Medline.txt")).unwrap();
+        assert_eq!(catalogue.journals.len(), 6);
+        let radiology = catalogue.get("0401260").unwrap();
+        assert_eq!(radiology.title, "Radiology");
+        assert_eq!(radiology.issn_online.as_deref(), Some("1527-1315"));
+
+        // The journal itself first, then the shortest titles
+        let found: Vec<&str> = catalogue
+            .find("radiology")
+            .iter()
+            .map(|journal| journal.nlm_id.as_str())
+            .collect();
+        assert_eq!(found, vec!["0401260", "9114774", "101674571"]);
+        let found = catalogue.find("abdom radiol");
+        assert_eq!(found[0].nlm_id, "101674571");
+        assert_eq!(catalogue.find("1527-1315")[0].nlm_id, "0401260");
+        assert_eq!(catalogue.find("1532-429x")[0].nlm_id, "9815616");
+        assert!(catalogue.find("cardiology").is_empty());
+
+        assert_eq!(
+            catalogue.get("101674571").unwrap().rss_link(),
+            "https://pubmed.ncbi.nlm.nih.gov/rss/journals/101674571/?limit=15&name=Abdom+Radiol+%28NY%29&utm_campaign=journals"
+        );
+    }
+}
@@ -1,8 +1,8 @@
 use crate::datastructs::PubmedFeed;
 use crate::datastructs::User;
 use commands::admin_command_handler;
-use commands::user_command_handler;
-use senders::TelegramSender;
+use commands::{Reply, user_command_handler};
+use senders::{ConsoleSender, Sender, TelegramSender};
 use serde::Serialize;
 use std::fs;
 use std::io;
@@ -10,17 +10,30 @@ use std::path::Path;
 use std::sync::Arc;
 use teloxide::RequestError;
 use teloxide::prelude::*;
-use teloxide::types::InputFile;
+use teloxide::net::Download;
+use teloxide::types::{Document, InputFile};
 
+pub mod archive;
 pub mod channelwrapper;
 pub mod commands;
 pub mod config;
 pub mod datastructs;
 pub mod db;
+pub mod eutils;
+pub mod export;
+pub mod formatrules;
 pub mod formatter;
+pub mod health;
+pub mod http;
+pub mod journals;
+pub mod links;
+pub mod opml;
 pub mod preset;
+pub mod refresh;
 pub mod rsshandler;
 pub mod senders;
+#[cfg(test)]
+mod testserver;
 
 #[allow(dead_code)]
 pub fn write_data<T>(data: &T, path: &str) -> io::Result<()>
@@ -48,12 +61,17 @@ pub async fn admin_message_handler(
     msg: Message,
     conn: Arc<tokio_rusqlite::Connection>,
 ) -> ResponseResult<()> {
-    let text = String::from(msg.text().unwrap_or(""));
-    let answerstring = conn
+    // Files are sent with the command as their caption
+    let text = String::from(msg.text().or(msg.caption()).unwrap_or(""));
+    let document = match msg.document() {
+        Some(document) => Some(download_document(&bot, document).await?),
+        None => None,
+    };
+    let reply = conn
         .call(move |conn| {
             let rt = tokio::runtime::Runtime::new().unwrap();
             rt.block_on(async {
-                admin_command_handler(&text, conn)
+                admin_command_handler(&text, document.as_deref(), conn)
                     .await
                     .map_err(tokio_rusqlite::Error::Other)
             })
@@ -61,16 +79,16 @@ pub async fn admin_message_handler(
         .await
         .map_err(|e| RequestError::Io(Arc::new(std::io::Error::other(e))))?;
 
-    if answerstring.len() > 4000 {
-        let document = InputFile::memory(answerstring).file_name("reply.txt");
-        bot.send_document(msg.chat.id, document)
-            .caption("Answer is provided in the file as it was too long.")
-            .send()
-            .await?;
-    } else {
-        TelegramSender::send_message_bot(&bot, msg.chat.id, &answerstring).await?;
-    }
-    Ok(())
+    send_reply(bot, msg.chat.id, reply).await
+}
+
+async fn download_document(bot: &Bot, document: &Document) -> ResponseResult<String> {
+    let file = bot.get_file(document.file.id.clone()).await?;
+    let mut content = Vec::new();
+    bot.download_file(&file.path, &mut content)
+        .await
+        .map_err(|e| RequestError::Io(Arc::new(std::io::Error::other(e))))?;
+    Ok(String::from_utf8_lossy(&content).to_string())
 }
 
 pub async fn user_message_handler(
@@ -89,7 +107,7 @@ pub async fn user_message_handler(
         full_name = Some(user.full_name())
     }
 
-    let answerstring = conn
+    let reply = conn
         .call(move |conn| {
             let mut ur = db::sqlite::get_user(conn, chat_id)?;
             if ur.is_none() {
@@ -112,14 +130,52 @@ pub async fn user_message_handler(
         .await
         .map_err(|e| RequestError::Io(Arc::new(std::io::Error::other(e))))?;
 
-    if answerstring.len() > 4000 {
-        let document = InputFile::memory(answerstring).file_name("reply.txt");
-        bot.send_document(msg.chat.id, document)
-            .caption("Answer is provided in the file as it was too long.")
-            .send()
-            .await?;
-    } else {
-        TelegramSender::send_message_bot(&bot, msg.chat.id, &answerstring).await?;
+    send_reply(bot, msg.chat.id, reply).await
+}
+
+async fn send_reply(bot: Bot, chat_id: ChatId, reply: Reply) -> ResponseResult<()> {
+    match reply {
+        Reply::Document {
+            file_name,
+            content,
+            caption,
+        } => {
+            let document = InputFile::memory(content).file_name(file_name);
+            bot.send_document(chat_id, document)
+                .caption(caption)
+                .send()
+                .await?;
+        }
+        Reply::Text(answerstring) if answerstring.len() > 4000 => {
+            let document = InputFile::memory(answerstring).file_name("reply.txt");
+            bot.send_document(chat_id, document)
+                .caption("Answer is provided in the file as it was too long.")
+                .send()
+                .await?;
+        }
+        Reply::Text(answerstring) => {
+            TelegramSender::send_message_bot(&bot, chat_id, &answerstring).await?;
+        }
+        Reply::Items { text, items } => {
+            TelegramSender::send_message_bot(&bot, chat_id, &text).await?;
+            let sender = TelegramSender::new(bot);
+            let user = User::new(chat_id.0);
+            for (item, item_metadata) in items.iter() {
+                // Errors are logged by the sender
+                let _ = sender.send_item(&user, item, item_metadata).await;
+            }
+        }
+        Reply::Notify { text, messages } => {
+            TelegramSender::send_message_bot(&bot, chat_id, &text).await?;
+            for (other_chat_id, message) in messages.iter() {
+                // A user that blocked the bot should not stop the others
+                if let Err(e) =
+                    TelegramSender::send_message_bot(&bot, ChatId(*other_chat_id), message).await
+                {
+                    log::error!("Could not notify user {}: {:?}", other_chat_id, e);
+                }
+            }
+        }
     }
     Ok(())
 }
@@ -135,7 +191,15 @@ pub async fn console_message_handler(
         ur = Some(User::new(chat_id));
         db::sqlite::add_user(conn, ur.as_ref().unwrap())?;
     }
-    match user_command_handler(text, &mut ur.unwrap(), conn).await {
+    let user = ur.unwrap();
+    match user_command_handler(text, &mut user.clone(), conn).await {
+        Ok(Reply::Items { text, items }) => {
+            println!("{}", text);
+            let sender = ConsoleSender::new();
+            for (item, item_metadata) in items.iter() {
+                let _ = sender.send_item(&user, item, item_metadata).await;
+            }
+        }
         Ok(response) => println!("{}", response),
         Err(e) => println!("Error: {e:?}. Try /help for the list of available commands."),
     }
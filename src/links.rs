@@ -0,0 +1,147 @@
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+// The links in the footer of a message. The admin can add links in the config file, eg:
+//
+// [[link]]
+// name = "Library"
+// url = "https://proxy.hospital.org/login?url=https://doi.org/{doi}"
+// default = false
+//
+// A url can contain {doi} and {pmid}: the link is left out for items without them.
+// A link with the name of a built in link replaces it. Users pick their links
+// with /setlinks, otherwise they get the default links.
+
+static LINK_TEMPLATES: OnceLock<Vec<LinkTemplate>> = OnceLock::new();
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LinkTemplate {
+    pub name: String,
+    pub url: String,
+    /// Shown to the users that did not pick their own links.
+    #[serde(default = "default_true")]
+    pub default: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl LinkTemplate {
+    pub fn new(name: &str, url: &str) -> LinkTemplate {
+        LinkTemplate {
+            name: name.to_string(),
+            url: url.to_string(),
+            default: true,
+        }
+    }
+
+    /// The url with the identifiers filled in, or None if the item lacks one of them.
+    pub fn fill(&self, doi: Option<&str>, pmid: Option<&str>) -> Option<String> {
+        let mut url = self.url.clone();
+        for (placeholder, value) in [("{doi}", doi), ("{pmid}", pmid)] {
+            if url.contains(placeholder) {
+                url = url.replace(placeholder, value?);
+            }
+        }
+        Some(url)
+    }
+}
+
+pub fn default_link_templates() -> Vec<LinkTemplate> {
+    vec![
+        LinkTemplate::new("Link", "https://doi.org/{doi}"),
+        LinkTemplate::new("PubMed", "https://pubmed.ncbi.nlm.nih.gov/{pmid}"),
+        LinkTemplate::new("QxMD", "https://qxmd.com/r/{pmid}"),
+    ]
+}
+
+/// Adds the links of the config file to the built in links.
+pub fn merge_link_templates(templates: &mut Vec<LinkTemplate>, extra: Vec<LinkTemplate>) {
+    for link in extra {
+        match templates
+            .iter_mut()
+            .find(|t| t.name.eq_ignore_ascii_case(&link.name))
+        {
+            Some(template) => *template = link,
+            None => templates.push(link),
+        }
+    }
+}
+
+/// Finds a link by name. Spaces can be entered as _.
+pub fn find_link<'a>(templates: &'a [LinkTemplate], name: &str) -> Option<&'a LinkTemplate> {
+    let name = name.replace('_', " ");
+    templates
+        .iter()
+        .find(|t| t.name.eq_ignore_ascii_case(&name))
+}
+
+/// The links picked by the user, in their order, or the default links.
+pub fn select_links<'a>(
+    templates: &'a [LinkTemplate],
+    chosen: Option<&[String]>,
+) -> Vec<&'a LinkTemplate> {
+    match chosen {
+        Some(names) => names
+            .iter()
+            .filter_map(|name| find_link(templates, name))
+            .collect(),
+        None => templates.iter().filter(|t| t.default).collect(),
+    }
+}
+
+/// Sets the links used by the formatter. Can only be set once, at startup.
+pub fn set_link_templates(templates: Vec<LinkTemplate>) -> Result<(), &'static str> {
+    LINK_TEMPLATES
+        .set(templates)
+        .map_err(|_| "The link templates were already set!")
+}
+
+/// The links set at startup, or the built in links.
+pub fn link_templates() -> &'static [LinkTemplate] {
+    LINK_TEMPLATES.get_or_init(default_link_templates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_templates() {
+        let mut templates = default_link_templates();
+        merge_link_templates(
+            &mut templates,
+            vec![
+                LinkTemplate {
+                    name: "Library".to_string(),
+                    url: "https://proxy.hospital.org/login?url=https://doi.org/{doi}".to_string(),
+                    default: false,
+                },
+                LinkTemplate::new("pubmed", "https://www.ncbi.nlm.nih.gov/pubmed/{pmid}"),
+            ],
+        );
+        assert_eq!(templates.len(), 4);
+
+        let names: Vec<&str> = select_links(&templates, None)
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Link", "pubmed", "QxMD"]);
+
+        let chosen = vec![
+            "library".to_string(),
+            "Unknown".to_string(),
+            "QxMD".to_string(),
+        ];
+        let links = select_links(&templates, Some(&chosen));
+        assert_eq!(links.len(), 2);
+        assert_eq!(
+            links[0]
+                .fill(Some("10.1148/radiol.240631"), None)
+                .as_deref(),
+            Some("https://proxy.hospital.org/login?url=https://doi.org/10.1148/radiol.240631")
+        );
+        assert_eq!(links[1].fill(Some("10.1148/radiol.240631"), None), None);
+    }
+}
@@ -1,11 +1,18 @@
+use chrono::Local;
 use log::LevelFilter;
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Root};
 use rss::Item;
-use rssnotify::commands::{AdminCommand, Command};
+use rssnotify::commands::{AdminCommand, Command, import_opml};
 use rssnotify::config::Config;
 use rssnotify::datastructs::{ItemMetadata, User};
+use rssnotify::eutils::set_eutils_base_url;
+use rssnotify::formatrules::{FormatRules, set_format_rules};
+use rssnotify::http::set_http_client;
+use rssnotify::journals::{JournalCatalogue, set_journal_catalogue};
+use rssnotify::links::set_link_templates;
+use rssnotify::refresh::set_refresh_settings;
 use rssnotify::senders::TelegramSender;
 use rssnotify::senders::{ConsoleSender, Sender};
 use rssnotify::{
@@ -54,11 +61,61 @@ async fn main() {
 
     config.log_structs();
 
+    if config.format_rules_path.is_file() {
+        match FormatRules::from_file(&config.format_rules_path) {
+            Ok(rules) => {
+                log::info!("Loaded {} journal formatting rules.", rules.journals.len());
+                let _ = set_format_rules(rules);
+            }
+            Err(e) => {
+                log::error!("Problem parsing the formatting rules: {e:?}");
+                process::exit(1);
+            }
+        }
+    } else {
+        log::info!("No formatting rules file found, using the default rules.");
+    }
+    if config.journal_catalogue_path.is_file() {
+        match JournalCatalogue::from_file(&config.journal_catalogue_path) {
+            Ok(catalogue) => {
+                log::info!("Loaded {} journals in the catalogue.", catalogue.journals.len());
+                let _ = set_journal_catalogue(catalogue);
+            }
+            Err(e) => {
+                log::error!("Problem reading the journal catalogue: {e:?}");
+                process::exit(1);
+            }
+        }
+    } else {
+        log::info!("No journal catalogue file found, /findjournal is disabled.");
+    }
+    let _ = set_link_templates(config.links.clone());
+    let _ = set_eutils_base_url(config.eutils_base_url.clone());
+    let _ = set_refresh_settings(config.refresh);
+    if let Err(e) = set_http_client(config.http.clone()) {
+        log::error!("Problem setting up the HTTP client: {e:?}");
+        process::exit(1);
+    }
+
     let conn = match config.db_path.is_file() {
         true => db::sqlite::open(config.db_path.to_str().unwrap()).unwrap(),
         false => make_db(&config.db_path).await.unwrap(),
     };
 
+    if let Some(path) = &config.import_opml {
+        let imported = match std::fs::read_to_string(path) {
+            Ok(opml) => import_opml(&conn, &opml),
+            Err(e) => Err(e.into()),
+        };
+        match imported {
+            Ok(r) => log::info!("Imported {}:\n{}", path.display(), r),
+            Err(e) => {
+                log::error!("Problem importing {}: {e:?}", path.display());
+                process::exit(1);
+            }
+        }
+    }
+
     let aconn = Connection::open(&config.db_path).await.unwrap();
     let arcconn = Arc::new(aconn);
 
@@ -178,13 +235,14 @@ where
             let arcconn = Arc::clone(&arcconn);
             let sender = sender.clone();
             Box::pin(async move {
+                // Downloads without holding the connection
+                if let Err(e) = db::sqlite::update_channels_async(&arcconn).await {
+                    log::error!("Error when updating the channels:\n{:?}", e)
+                }
                 if let Err(e) = arcconn
                     .call(move |conn| {
                         let rt = tokio::runtime::Runtime::new().unwrap();
                         rt.block_on(async {
-                            db::sqlite::update_channels(conn)
-                                .await
-                                .map_err(tokio_rusqlite::Error::Rusqlite)?;
                             send_new_users(conn, &sender)
                                 .await
                                 .map_err(tokio_rusqlite::Error::Rusqlite)
@@ -210,10 +268,13 @@ async fn send_new_users<S: Sender>(
     log::info!("Sending new items to all users");
     let users = db::sqlite::get_users(conn)?;
     let mut new_items: BTreeMap<u32, Vec<&Item>> = BTreeMap::new();
-    let mut feeds = db::sqlite::get_feeds(conn)?;
+    let feeds = db::sqlite::get_feeds(conn)?;
 
     for feed in feeds.iter() {
-        new_items.insert(feed.uid.unwrap(), feed.get_new_items_from_last());
+        if let Some(uid) = feed.uid {
+            let seen = db::sqlite::get_seen_items(conn, uid)?;
+            new_items.insert(uid, feed.get_unseen_items(&seen));
+        }
     }
 
     let mut result = Vec::new();
@@ -222,11 +283,14 @@ async fn send_new_users<S: Sender>(
         let _ = db::sqlite::update_user(conn, user);
     }
 
-    for feed in feeds.iter_mut() {
-        feed.update_guid();
+    let now = Local::now().timestamp();
+    for feed in feeds.iter() {
+        if let Some(uid) = feed.uid {
+            let ids: Vec<u32> = feed.channel.item_ids().iter().map(|(_, id)| *id).collect();
+            db::sqlite::mark_seen(conn, uid, &ids, now)?;
+        }
     }
-
-    db::sqlite::update_guid_feeds(conn, &feeds)?;
+    db::sqlite::prune_seen_items(conn, now)?;
     for r in result {
         r?;
     }
@@ -241,19 +305,22 @@ async fn send_new_user<S: Sender>(
 ) -> Result<usize, rusqlite::Error> {
     for (index, collection) in user.rss_lists.iter().enumerate() {
         // TODO: add possibility to include keyword
-        let item_metadata = ItemMetadata {
+        let mut item_metadata = ItemMetadata {
             collection: Some(index),
+            style: collection.style.clone(),
+            links: user.links.clone(),
             ..Default::default()
         };
         for feed_id in collection.feeds.iter() {
             if let Some(items) = new_items.get(feed_id) {
-                // Make new vec with references to the items
-                let filtered: Vec<&Item> = items
-                    .iter()
-                    .copied()
-                    .filter(|item| collection.filter_item(item))
-                    .collect();
-                sender.send_items(user, &filtered, &item_metadata).await;
+                item_metadata.feed_id = Some(*feed_id);
+                for item in items.iter() {
+                    // The matched keywords are highlighted in the message
+                    if let Some(matched) = collection.matched_keywords(item) {
+                        item_metadata.highlights = matched;
+                        let _ = sender.send_item(user, item, &item_metadata).await;
+                    }
+                }
             }
         }
     }
@@ -0,0 +1,122 @@
+use quick_xml::Reader;
+use quick_xml::encoding::Decoder;
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use std::error::Error;
+
+// OPML is the file format RSS readers use to import and export their
+// subscriptions. Every outline with an xmlUrl is a feed, the other outlines
+// are folders: the collections of a user in the exported files.
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OpmlFeed {
+    pub name: String,
+    pub link: String,
+}
+
+/// The feeds in an OPML file, in folders or not.
+pub fn parse_opml(xml: &str) -> Result<Vec<OpmlFeed>, Box<dyn Error + Sync + Send>> {
+    let mut reader = Reader::from_str(xml);
+    let mut feeds = Vec::new();
+    let mut found_opml = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) | Event::Empty(element) => {
+                let decoder = reader.decoder();
+                match element.name().as_ref() {
+                    b"opml" => found_opml = true,
+                    b"outline" => {
+                        if let Some(link) = attribute(&element, "xmlUrl", decoder)?
+                            .filter(|link| !link.trim().is_empty())
+                        {
+                            let name = attribute(&element, "title", decoder)?
+                                .or(attribute(&element, "text", decoder)?)
+                                .filter(|name| !name.trim().is_empty())
+                                .unwrap_or(link.clone());
+                            feeds.push(OpmlFeed {
+                                name: name.trim().to_string(),
+                                link: link.trim().to_string(),
+                            });
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    if !found_opml {
+        return Err("The file is not an OPML file.".into());
+    }
+    Ok(feeds)
+}
+
+fn attribute(
+    element: &BytesStart,
+    name: &str,
+    decoder: Decoder,
+) -> Result<Option<String>, Box<dyn Error + Sync + Send>> {
+    match element.try_get_attribute(name)? {
+        Some(attr) => Ok(Some(attr.decode_and_unescape_value(decoder)?.to_string())),
+        None => Ok(None),
+    }
+}
+
+/// An OPML file with a folder for every group of feeds.
+pub fn to_opml(title: &str, groups: &[(String, Vec<OpmlFeed>)]) -> String {
+    let mut r =
+        String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n");
+    r.push_str(&format!(
+        "  <head>\n    <title>{}</title>\n  </head>\n  <body>\n",
+        escape(title)
+    ));
+    for (group, feeds) in groups.iter() {
+        r.push_str(&format!(
+            "    <outline text=\"{}\" title=\"{}\">\n",
+            escape(group),
+            escape(group)
+        ));
+        for feed in feeds.iter() {
+            r.push_str(&format!(
+                "      <outline type=\"rss\" text=\"{}\" title=\"{}\" xmlUrl=\"{}\"/>\n",
+                escape(&feed.name),
+                escape(&feed.name),
+                escape(&feed.link)
+            ));
+        }
+        r.push_str("    </outline>\n");
+    }
+    r.push_str("  </body>\n</opml>\n");
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opml() {
+        let xml = std::fs::read_to_string("test/feeds.opml").unwrap();
+        let feeds = parse_opml(&xml).unwrap();
+        assert_eq!(feeds.len(), 3);
+        assert_eq!(feeds[0].name, "Insights Imaging");
+        assert_eq!(
+            feeds[0].link,
+            "https://pubmed.ncbi.nlm.nih.gov/rss/journals/101532453/?limit=15&name=Insights%20Imaging&utm_campaign=journals"
+        );
+        // Without a title the text is the name
+        assert_eq!(feeds[1].name, "Neuroradiology");
+        // Without a title and text the link is the name
+        assert_eq!(feeds[2].name, feeds[2].link);
+
+        let groups = vec![("Collection 0 & more".to_string(), feeds.clone())];
+        let opml = to_opml("rssnotify", &groups);
+        assert!(opml.contains("<outline text=\"Collection 0 &amp; more\""));
+        // The same feeds after a round trip
+        assert_eq!(parse_opml(&opml).unwrap(), feeds);
+
+        assert!(parse_opml("<rss><channel></channel></rss>").is_err());
+        assert!(parse_opml("<opml><body><outline").is_err());
+    }
+}
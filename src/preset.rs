@@ -26,7 +26,7 @@ pub enum Keywords {
     AIBlacklist,
 }
 
-#[derive(Debug, EnumIter, EnumString)]
+#[derive(Debug, Clone, Copy, EnumIter, EnumString)]
 pub enum Journals {
     #[strum(ascii_case_insensitive)]
     Radiology,
@@ -170,6 +170,11 @@ pub fn get_preset_journals(journals: Journals) -> HashSet<u32> {
     .collect::<HashSet<u32>>()
 }
 
+/// The first journal preset with the feed, eg. to keep it from being deleted.
+pub fn journal_preset_with(feed_id: u32) -> Option<Journals> {
+    Journals::iter().find(|journals| get_preset_journals(*journals).contains(&feed_id))
+}
+
 pub fn merge_keyword_preset_with_set(keywords: Keywords, set: &HashSet<String>) -> HashSet<String> {
     get_preset_keywords(keywords)
         .into_iter()
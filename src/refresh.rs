@@ -0,0 +1,184 @@
+use chrono::Local;
+use futures::StreamExt;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::channelwrapper::ChannelWrapper;
+use crate::datastructs::{ChannelUpdate, PubmedFeed};
+
+// Downloads the feeds concurrently. At most `max_concurrent` feeds are
+// downloaded at the same time, and a feed that does not answer within the
+// timeout is skipped until the next update, so one slow server does not
+// hold up the others. Feeds that keep failing are skipped, see `health`.
+
+static REFRESH_SETTINGS: OnceLock<RefreshSettings> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefreshSettings {
+    pub max_concurrent: usize,
+    pub timeout: Duration,
+}
+
+impl Default for RefreshSettings {
+    fn default() -> Self {
+        RefreshSettings {
+            max_concurrent: 4,
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Sets the download limits. Can only be set once, at startup.
+pub fn set_refresh_settings(settings: RefreshSettings) -> Result<(), &'static str> {
+    REFRESH_SETTINGS
+        .set(settings)
+        .map_err(|_| "The refresh settings were already set!")
+}
+
+/// The settings set at startup, or the defaults.
+pub fn refresh_settings() -> &'static RefreshSettings {
+    REFRESH_SETTINGS.get_or_init(RefreshSettings::default)
+}
+
+/// The feeds that need to be saved after a refresh, by index.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Refreshed {
+    /// Downloaded with a new channel: the channel and health are saved.
+    pub channels: Vec<usize>,
+    /// Not modified or failed: only the health is saved.
+    pub health: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Skipped,
+    Health,
+    Channel,
+}
+
+/// Updates the channels of the feeds. Returns the feeds that changed and
+/// need to be saved. Errors and timeouts are logged and recorded in the
+/// health of the feed.
+pub async fn refresh_feeds(feeds: &mut [PubmedFeed], settings: &RefreshSettings) -> Refreshed {
+    let updates: Vec<_> = feeds
+        .iter_mut()
+        .enumerate()
+        .map(|(index, feed)| refresh_feed(index, feed, settings.timeout))
+        .collect();
+    let mut results: Vec<(usize, Outcome)> = futures::stream::iter(updates)
+        .buffer_unordered(settings.max_concurrent.max(1))
+        .collect()
+        .await;
+    results.sort_by_key(|(index, _)| *index);
+    let mut refreshed = Refreshed::default();
+    for (index, outcome) in results {
+        match outcome {
+            Outcome::Skipped => {}
+            Outcome::Health => refreshed.health.push(index),
+            Outcome::Channel => refreshed.channels.push(index),
+        }
+    }
+    refreshed
+}
+
+/// Returns the index, and what changed in the feed.
+async fn refresh_feed(index: usize, feed: &mut PubmedFeed, timeout: Duration) -> (usize, Outcome) {
+    if feed.health.backing_off(Local::now()) {
+        log::debug!(
+            "Feed {} failed {} times, skipping update.",
+            feed.name,
+            feed.health.failures
+        );
+        return (index, Outcome::Skipped);
+    }
+    let previous_ids: HashSet<u32> = feed
+        .channel
+        .items()
+        .iter()
+        .filter_map(ChannelWrapper::item_id)
+        .collect();
+    let update = tokio::time::timeout(timeout, feed.update_channel_limited()).await;
+    let now = Local::now();
+    let outcome = match update {
+        Ok(Ok(ChannelUpdate::Skipped)) => Outcome::Skipped,
+        Ok(Ok(ChannelUpdate::NotModified)) => {
+            let item_count = feed.channel.items().len();
+            feed.health.record_success(now, item_count, false);
+            Outcome::Health
+        }
+        Ok(Ok(ChannelUpdate::Modified)) => {
+            let items = feed.channel.items();
+            let new_items = items
+                .iter()
+                .filter_map(ChannelWrapper::item_id)
+                .any(|id| !previous_ids.contains(&id));
+            feed.health.record_success(now, items.len(), new_items);
+            feed.health.malformed_items = feed.log_malformed_items() as u32;
+            Outcome::Channel
+        }
+        Ok(Err(e)) => {
+            log::error!("Error when updating feed {}:\n{:?}", feed.name, e);
+            feed.health.record_failure(now, &e.to_string());
+            Outcome::Health
+        }
+        Err(_) => {
+            log::error!(
+                "Updating feed {} timed out after {} seconds",
+                feed.name,
+                timeout.as_secs_f32()
+            );
+            let error = format!("timed out after {} seconds", timeout.as_secs_f32());
+            feed.health.record_failure(now, &error);
+            Outcome::Health
+        }
+    };
+    (index, outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testserver::{self, Response};
+
+    #[tokio::test]
+    async fn test_refresh_feeds() {
+        let base_url = testserver::serve(3, |request| {
+            if request.starts_with("GET /slow") {
+                std::thread::sleep(Duration::from_secs(2));
+            }
+            Response::ok(
+                r#"<rss version="2.0"><channel><title>Journal</title><link>https://journal.example.org</link><description></description>
+                <item><title>An article</title><guid>https://journal.example.org/1</guid></item>
+                </channel></rss>"#,
+            )
+        });
+        let mut feeds: Vec<PubmedFeed> = ["fast1", "slow", "fast2"]
+            .iter()
+            .map(|path| {
+                let mut feed =
+                    PubmedFeed::build_from_link(&format!("{base_url}/{path}"), path).unwrap();
+                feed.subscribers = 1;
+                feed
+            })
+            .collect();
+        let settings = RefreshSettings {
+            max_concurrent: 2,
+            timeout: Duration::from_millis(500),
+        };
+        let refreshed = refresh_feeds(&mut feeds, &settings).await;
+        assert_eq!(refreshed.channels, vec![0, 2]);
+        // The failed feed is saved too, for its health
+        assert_eq!(refreshed.health, vec![1]);
+        assert_eq!(feeds[0].channel.items().len(), 1);
+        assert_eq!(feeds[0].health.item_count, Some(1));
+        assert!(feeds[0].health.last_new_item.is_some());
+        assert!(feeds[1].channel.items().is_empty());
+        assert_eq!(feeds[1].health.failures, 1);
+        assert!(feeds[1].health.backing_off(Local::now()));
+
+        // Backing off: not downloaded, the server is not asked again
+        let refreshed = refresh_feeds(&mut feeds[1..2], &settings).await;
+        assert_eq!(refreshed, Refreshed::default());
+    }
+}
@@ -3,19 +3,20 @@ use rss::Item;
 use std::collections::HashSet;
 use std::error::Error;
 
+use crate::http::http_client;
+
 // "https://pubmed.ncbi.nlm.nih.gov/rss/journals/101532453/?limit=15&name=Insights%20Imaging&utm_campaign=journals"
 
 pub async fn get_channel(link: &str) -> Result<Channel, Box<dyn Error>> {
-    let content = reqwest::get(link).await?.bytes().await?;
+    let client = http_client();
+    let content = client.send(client.get(link)).await?.bytes().await?;
     let channel = Channel::read_from(&content[..])?;
     Ok(channel)
 }
 
 pub fn item_contains_keyword(item: &Item, keywords: &HashSet<String>) -> bool {
     for keyword in keywords {
-        if item.content().unwrap_or("").contains(keyword)
-            | item.title().unwrap_or("").to_lowercase().contains(keyword)
-        {
+        if keyword_in_item(item, keyword) {
             log::debug!("Keyword matched: {keyword}");
             return true;
         }
@@ -23,6 +24,24 @@ pub fn item_contains_keyword(item: &Item, keywords: &HashSet<String>) -> bool {
     false
 }
 
+/// All the keywords found in the item, eg. to highlight them in the message.
+pub fn matched_keywords(item: &Item, keywords: &HashSet<String>) -> Vec<String> {
+    let mut matched: Vec<String> = keywords
+        .iter()
+        .filter(|keyword| keyword_in_item(item, keyword))
+        .cloned()
+        .collect();
+    matched.sort();
+    matched
+}
+
+/// Generic RSS and Atom items often only have a description (summary).
+fn keyword_in_item(item: &Item, keyword: &str) -> bool {
+    item.content().unwrap_or("").contains(keyword)
+        | item.description().unwrap_or("").contains(keyword)
+        | item.title().unwrap_or("").to_lowercase().contains(keyword)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -58,4 +77,34 @@ mod tests {
         assert!(!item_contains_keyword(&item, &keywords));
         assert!(!item_contains_keyword(&item, &keywords2));
     }
+
+    #[test]
+    fn description_test() {
+        let item = ItemBuilder::default()
+            .title("A journal article".to_string())
+            .description("Interreader agreement of Lung-RADS.".to_string())
+            .build();
+        let keywords = vec!["agreement".to_string()].into_iter().collect();
+        assert!(item_contains_keyword(&item, &keywords));
+        assert_eq!(
+            matched_keywords(&item, &keywords),
+            vec!["agreement".to_string()]
+        );
+    }
+
+    #[test]
+    fn matched_keywords_test() {
+        let item = ItemBuilder::default()
+            .title("Lung-RADS in Practice".to_string())
+            .content("Interreader agreement of Lung-RADS.".to_string())
+            .build();
+        let keywords = vec!["agreement", "lung-rads", "prostate"]
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            matched_keywords(&item, &keywords),
+            vec!["agreement".to_string(), "lung-rads".to_string()]
+        );
+    }
 }
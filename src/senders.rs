@@ -8,9 +8,12 @@ use teloxide::utils::markdown;
 use teloxide::{Bot, types::ChatId};
 
 use crate::datastructs::{ItemMetadata, User};
-use crate::formatter::PreppedMessage;
+use crate::formatter::{MessageFormat, PreppedMessage};
 
+#[allow(async_fn_in_trait)]
 pub trait Sender {
+    /// The format the items are rendered to before they are sent.
+    fn message_format(&self) -> MessageFormat;
     async fn send_item(
         &self,
         user: &User,
@@ -41,6 +44,10 @@ impl ConsoleSender {
 }
 
 impl Sender for ConsoleSender {
+    fn message_format(&self) -> MessageFormat {
+        MessageFormat::PlainText
+    }
+
     async fn send_item(
         &self,
         user: &User,
@@ -55,7 +62,7 @@ impl Sender for ConsoleSender {
         );
         println!(
             "{}",
-            PreppedMessage::build(item, item_metadata).format(ParseMode::MarkdownV2)
+            PreppedMessage::build(item, item_metadata).render(self.message_format())
         );
 
         Ok(())
@@ -124,28 +131,33 @@ impl TelegramSender {
 }
 
 impl Sender for TelegramSender {
+    fn message_format(&self) -> MessageFormat {
+        MessageFormat::MarkdownV2
+    }
+
     async fn send_item(
         &self,
         user: &User,
         item: &Item,
         item_metadata: &ItemMetadata,
     ) -> Result<(), Box<dyn Error + Sync + Send>> {
-        if item.content().is_some() {
-            let formatted =
-                PreppedMessage::build(item, item_metadata).format(ParseMode::MarkdownV2);
-            log::trace!("Sending the following item to userid {}", user.chat_id);
-            log::trace!("{}", formatted);
-            let result = self.send_message(ChatId(user.chat_id), &formatted).await;
-            if let Err(e) = result {
-                log::error!("Error when sending an item: {e:?}");
-                Err(e)?;
-            }
-            Ok(())
-        } else {
-            // TODO titel ofzo ook mee in de warning
-            log::warn!("Item did not have content!");
-            Err("Item did not have content".into())
+        if item.content().is_none() {
+            // Still sent, with only the title and links
+            log::warn!(
+                "Item in feed {:?} did not have content: {}",
+                item_metadata.feed_id,
+                item.title().unwrap_or("no title")
+            );
+        }
+        let formatted = PreppedMessage::build(item, item_metadata).render(self.message_format());
+        log::trace!("Sending the following item to userid {}", user.chat_id);
+        log::trace!("{}", formatted);
+        let result = self.send_message(ChatId(user.chat_id), &formatted).await;
+        if let Err(e) = result {
+            log::error!("Error when sending an item: {e:?}");
+            Err(e)?;
         }
+        Ok(())
     }
 
     async fn send_items(
@@ -0,0 +1,77 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+
+// A stand-in HTTP server for the tests, so they do not depend on PubMed or
+// other servers being reachable.
+
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    pub fn ok(body: &str) -> Response {
+        Response {
+            status: 200,
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    pub fn status(status: u16) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// Answers the given number of requests with the response of `respond`, which
+/// gets the request line and headers. Each request is answered in its own
+/// thread, so a slow response does not hold up the others.
+/// Returns the base url of the server.
+pub fn serve<F>(requests: usize, respond: F) -> String
+where
+    F: Fn(&str) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let respond = Arc::new(respond);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(requests) {
+            let respond = Arc::clone(&respond);
+            std::thread::spawn(move || answer(stream.unwrap(), respond.as_ref()));
+        }
+    });
+    format!("http://{}", address)
+}
+
+fn answer<F>(mut stream: TcpStream, respond: &F)
+where
+    F: Fn(&str) -> Response,
+{
+    let mut request = String::new();
+    let mut reader = BufReader::new(&stream);
+    // Up to the empty line after the headers
+    while reader.read_line(&mut request).unwrap() > 0 && !request.ends_with("\r\n\r\n") {}
+    let response = respond(&request);
+    let mut head = format!("HTTP/1.1 {} Stand-in\r\n", response.status);
+    for (name, value) in response.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    ));
+    // The client is gone if it timed out
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(response.body.as_bytes());
+}